@@ -35,14 +35,14 @@ pub fn load_songs(song_path: &str) {
                 continue;
             };
 
-            let osu_file_reader = BufReader::new(File::open(&file.path()).unwrap());    
+            let osu_file_reader = BufReader::new(File::open(file.path()).unwrap());    
             if let Ok(i) = Beatmap::parse(osu_file_reader) {
                 let name_artist = format!("{}-{}", i.title, i.artist);
                 if used_songs.iter().any(|e| e == &name_artist) {
                     continue;
                 }
                 used_songs.push(name_artist);
-                let _path = folder.path().to_string_lossy().replace(&song_path, "");
+                let _path = folder.path().to_string_lossy().replace(song_path, "");
                 mp3_ratio -= 1;
                 if mp3_ratio == 0 {
                     break;
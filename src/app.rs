@@ -1,8 +1,29 @@
+use std::io;
+use std::sync::mpsc::{self, Sender, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
 use serde::{Serialize, Deserialize};
-use crossterm::event::{KeyEvent, KeyCode};
+use crossterm::event::{KeyEvent, KeyCode, MouseEvent, MouseEventKind, MouseButton};
 
-use crate::osu::{Song, Mod};
-use crate::player::{Playlist, Player, serialize_playlists, PlaybarSource};
+use crate::osu::{self, Song, SongId, Entry, Mod};
+use crate::library::Library;
+use crate::player::{Playlist, Player, QueueRow, serialize_playlists};
+use crate::serialize;
+use crate::visualizer::VisualizerMode;
+use crate::export::{ExportFormat, export_song};
+use crate::playlist_files::{PlaylistFormat, export_playlist, import_playlist, write_playlist};
+use crate::query::{Query, QueryContext, SortKey, sort_songs};
+use crate::stats::{Stats, Period};
+use crate::session::{Session, save_session, load_session};
+use crate::user_data::{UserData, parse_tags};
+use crate::undo::{UndoStack, Snapshot};
+use crate::keymap::{Keymap, Section, Action};
+use crate::command::{Command, CommandLine, expand_home};
+use crate::theme::Theme;
+use crate::renderer::{Areas, list_index};
+use crate::layout::{PanelLayout, Panel};
+use tui::layout::Rect;
 
 #[derive(Copy, Clone, Debug)]
 pub enum UIMode {
@@ -11,94 +32,695 @@ pub enum UIMode {
     Playlist,
 }
 
+/// how many old copies of the playlists file are kept
+const PLAYLIST_BACKUPS: usize = 3;
+
+/// how much + and - change the rate of a song by
+const RATE_STEP: f32 = 0.05;
+
+/// two clicks on the same line closer together than this are a double click
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// what the text typed into the playlist box is for
+#[derive(Clone, Debug)]
+pub enum ListInput {
+    New,
+    Rename(usize),
+    Import,
+    Smart,
+    EditQuery(usize),
+    Tags(SongId),
+    Note(SongId),
+}
+
+/// destructive actions which wait for the user to press y
+#[derive(Copy, Clone, Debug)]
+pub enum ConfirmAction {
+    DeletePlaylist(usize),
+    RemoveSong(usize, usize),
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AppData {
     pub song_path: String,
     pub serialized_songs: bool,
     pub serialize_path: String,
     pub playlist_path: String,
+    #[serde(default = "default_export_path")]
+    pub export_path: String,
+    #[serde(default = "default_session_path")]
+    pub session_path: String,
+    #[serde(default = "default_stats_path")]
+    pub stats_path: String,
+    #[serde(default = "default_user_data_path")]
+    pub user_data_path: String,
+    #[serde(default = "default_keymap_path")]
+    pub keymap_path: String,
+    #[serde(default = "default_command_history_path")]
+    pub command_history_path: String,
+    #[serde(default = "default_theme_path")]
+    pub theme_path: String,
+    #[serde(default = "default_layout_path")]
+    pub layout_path: String,
+}
+
+fn default_export_path() -> String {
+    String::from("exports/")
 }
 
-pub struct App<'a, 'b> {
+fn default_session_path() -> String {
+    String::from("assets/session.json")
+}
+
+fn default_stats_path() -> String {
+    String::from("assets/plays.jsonl")
+}
+
+fn default_user_data_path() -> String {
+    String::from("assets/user_data.json")
+}
+
+fn default_keymap_path() -> String {
+    String::from("assets/keys.json")
+}
+
+fn default_command_history_path() -> String {
+    String::from("assets/command_history.json")
+}
+
+fn default_theme_path() -> String {
+    String::from("assets/theme.json")
+}
+
+fn default_layout_path() -> String {
+    String::from("assets/layout.json")
+}
+
+/// THE LOADED FILE, OR THE DEFAULT WITH WHY IT WASNT USED ADDED TO errors
+fn or_default<T: Default>(loaded: Result<T, String>, fallback: &str, errors: &mut Vec<String>) -> T {
+    loaded.unwrap_or_else(|e| {
+        errors.push(format!("{}, {}", e, fallback));
+        T::default()
+    })
+}
+
+pub struct App {
     pub current_ui: UIMode,
     pub query: String,
-    pub queried_songs: Vec<&'a Song>,
-    pub playlists: Vec<Playlist<'b>>,
+    pub queried_songs: Vec<usize>,
+    pub playlists: Vec<Playlist>,
     pub playlist_i: usize,
     pub query_i: usize,
+    /// the mod songs get when theyre added from the search
+    pub search_mod: Mod,
+    pub sort: SortKey,
     pub new_playlist_name: String,
     pub is_adding_list: bool,
+    pub list_input: ListInput,
+    pub playlist_song_i: usize,
+    pub grabbed_song: bool,
+    pub confirming: Option<ConfirmAction>,
+    playlists_changed: bool,
+    /// false when the playlists file was there but couldnt be read, so it isnt saved over
+    playlists_loaded: bool,
+    backed_up: bool,
     pub glob_data: AppData,
-    pub player: Player<'a>,
-    all_songs: Vec<Song>,
+    pub player: Player,
+    pub visualizer: VisualizerMode,
+    pub status: String,
+    status_sender: Sender<String>,
+    status_receiver: Receiver<String>,
+    pub library: Library,
+    pub stats: Stats,
+    /// the stats popup is open while this is set
+    pub stats_period: Option<Period>,
+    /// the help popup is open while this is set, its how far it is scrolled
+    pub help_scroll: Option<usize>,
+    pub user_data: UserData,
+    user_data_changed: bool,
+    /// false if the file couldnt be read, so it isnt saved over
+    user_data_loaded: bool,
+    undo_stack: UndoStack,
+    pub keymap: Keymap,
+    pub command_line: CommandLine,
+    pub theme: Theme,
+    pub layout: PanelLayout,
+    /// where the panels were drawn, for working out what was clicked
+    pub areas: Areas,
+    // when and on which line the last click was, for spotting double clicks
+    last_click: Option<(Instant, Panel, u16)>,
+    library_sender: Sender<Vec<Song>>,
+    library_receiver: Receiver<Vec<Song>>,
 }
 
-impl<'a, 'b> App<'a, 'b> {
-    pub fn new(songs: Vec<Song>, glob_data: AppData, playlists: Vec<Playlist<'b>>) -> Self {
+impl App {
+    pub fn new(library: Library, glob_data: AppData, playlists: Vec<Playlist>) -> Self {
         let player = Player::new(glob_data.song_path.clone());
-        Self {
+        let stats = Stats::load(&glob_data.stats_path);
+        // a broken file falls back to the default rather than leaving things half set up
+        let mut load_errors = Vec::new();
+        let user_data = UserData::load(&glob_data.user_data_path);
+        let user_data_loaded = user_data.is_ok();
+        let user_data = or_default(user_data, "ratings won't be saved", &mut load_errors);
+        let keymap = or_default(Keymap::load(&glob_data.keymap_path), "using the default keys", &mut load_errors);
+        let theme = or_default(Theme::load(&glob_data.theme_path), "using the default theme", &mut load_errors);
+        let layout = or_default(PanelLayout::load(&glob_data.layout_path), "using the default layout", &mut load_errors);
+        // losing the history isnt worth a warning, it just starts again
+        let history = serialize::deserialize(&glob_data.command_history_path).unwrap_or_default();
+        let (status_sender, status_receiver) = mpsc::channel();
+        let (library_sender, library_receiver) = mpsc::channel();
+        let mut app = Self {
             query: String::new(),
-            all_songs: songs,
+            library,
             queried_songs: Vec::new(),
             query_i: 0,
+            search_mod: Mod::NoMod,
+            sort: SortKey::Library,
             current_ui: UIMode::PlayBar,
             playlists,
             playlist_i: 0,
             new_playlist_name: String::new(),
             is_adding_list: false,
+            list_input: ListInput::New,
+            playlist_song_i: 0,
+            grabbed_song: false,
+            confirming: None,
+            playlists_changed: false,
+            playlists_loaded: true,
+            backed_up: false,
             glob_data,
             player,
+            visualizer: VisualizerMode::Off,
+            status: String::new(),
+            status_sender,
+            status_receiver,
+            stats,
+            stats_period: None,
+            help_scroll: None,
+            user_data,
+            user_data_changed: false,
+            user_data_loaded,
+            undo_stack: UndoStack::default(),
+            keymap,
+            command_line: CommandLine::new(history),
+            theme,
+            layout,
+            areas: Areas::default(),
+            last_click: None,
+            library_sender,
+            library_receiver,
+        };
+        if !load_errors.is_empty() {
+            app.status = load_errors.join(" | ");
+        }
+        // the playbar has focus to start with but the layout might hide it
+        if app.layout.is_hidden(app.focused_panel()) {
+            app.focus_next_panel();
+        }
+        app.refresh_smart_playlists();
+        app
+    }
+
+    /// FILLS SMART PLAYLISTS AGAIN FROM THE LIBRARY, PLAY COUNTS AND RATINGS
+    fn refresh_smart_playlists(&mut self) {
+        let context = QueryContext::new(&self.stats, &self.user_data);
+        for playlist in self.playlists.iter_mut() {
+            playlist.refresh(&self.library, &context);
+        }
+    }
+
+    /// SAVES PLAYS THAT HAVE ENDED SINCE THE LAST LOOP
+    fn record_plays(&mut self) {
+        if self.player.plays.is_empty() {
+            return;
+        }
+        for play in std::mem::take(&mut self.player.plays) {
+            if let Err(e) = self.stats.record(play, &self.glob_data.stats_path) {
+                self.status = format!("couldn't save play history: {}", e);
+            }
+        }
+        self.refresh_smart_playlists();
+    }
+
+    /// THE SONG PLAYING AT QUIT NEVER GETS TO STOP, SO ITS LISTEN IS SAVED AS A SKIP HERE
+    pub fn record_current_play(&mut self) {
+        self.player.record_play(false);
+        self.record_plays();
+    }
+
+    /// PICKS UP MESSAGES AND RESCANS FROM WORK DONE IN OTHER THREADS
+    pub fn poll_status(&mut self) {
+        while let Ok(message) = self.status_receiver.try_recv() {
+            self.status = message;
+        }
+        self.record_plays();
+        while let Ok(songs) = self.library_receiver.try_recv() {
+            self.library.replace(songs);
+            // songs on a drive that wasnt there before can turn up again
+            for playlist in self.playlists.iter_mut() {
+                self.playlists_changed |= playlist.resolve_missing(&self.library);
+            }
+            self.refresh_smart_playlists();
+            let _ = serialize::serialize(&self.library.songs(), &self.glob_data.serialize_path);
+            self.get_matching_songs();
+            self.status = format!("library rescanned, {} songs", self.library.songs().len());
+        }
+    }
+
+    /// SAVES THE PLAYLISTS IF ANYTHING HAS CHANGED SINCE THE LAST SAVE
+    pub fn save_if_changed(&mut self) {
+        if self.playlists_changed {
+            self.save_playlists();
+        }
+        if self.user_data_changed {
+            self.save_user_data();
+        }
+    }
+
+    /// WRITES THE FAVOURITES AND RATINGS UNLESS THE FILE COULDNT BE READ AT STARTUP
+    pub fn save_user_data(&mut self) {
+        self.user_data_changed = false;
+        if !self.user_data_loaded {
+            return;
+        }
+        if let Err(e) = self.user_data.save(&self.glob_data.user_data_path) {
+            self.status = format!("couldn't save ratings: {}", e);
+        }
+    }
+
+    /// SMART PLAYLISTS AND THE SEARCH CAN DEPEND ON RATINGS SO BOTH ARE REDONE
+    fn user_data_edited(&mut self) {
+        self.user_data_changed = true;
+        self.refresh_smart_playlists();
+        self.get_matching_songs();
+    }
+
+    fn toggle_favourite(&mut self, id: &str) {
+        let favourite = self.user_data.toggle_favourite(id);
+        self.status = String::from(if favourite { "added to favourites" } else { "removed from favourites" });
+        self.user_data_edited();
+    }
+
+    fn set_rating(&mut self, id: &str, rating: u8) {
+        self.user_data.set_rating(id, rating);
+        self.user_data_edited();
+    }
+
+    /// REMEMBERS AN EDIT SO IT CAN BE UNDONE, WHAT CHANGED IS WORKED OUT FROM WHAT WAS SAVED BEFORE IT
+    fn record(&mut self, name: String, before: Vec<Snapshot>) {
+        let after = before.iter().map(|s| s.retake(&self.player, &self.playlists)).collect();
+        self.record_edit(name, before, after);
+    }
+
+    fn record_edit(&mut self, name: String, before: Vec<Snapshot>, after: Vec<Snapshot>) {
+        self.status = name.clone();
+        self.undo_stack.record(name, before, after);
+    }
+
+    /// THE NAME OF A SONG FOR THE STATUS LINE, THE ID IF ITS GONE FROM THE LIBRARY
+    fn song_name(&self, id: &str) -> String {
+        self.library.get(id).map_or(id, |s| s.song_name.as_str()).to_string()
+    }
+
+    /// WHAT HAS TO BE KEPT TO PUT A PLAYBAR ROW BACK HOW IT IS NOW
+    fn row_snapshot(&self, row: QueueRow) -> Option<Snapshot> {
+        match row {
+            QueueRow::Playing => self.player.playing.clone().map(Snapshot::Playing),
+            QueueRow::Queued(_) => Some(Snapshot::Queue(self.player.queue.clone())),
+            QueueRow::Playlist(_) => {
+                let i = self.player.playing_playlist?;
+                Some(Snapshot::Playback(i, self.playlists.get(i)?.playback_state()))
+            }
+            QueueRow::History(_) => Some(Snapshot::History(self.player.history.clone())),
+        }
+    }
+
+    /// CHANGES THE HOVERED PLAYBAR SONG SO THAT IT CAN BE UNDONE
+    fn edit_hovered_row(&mut self, action: &str, edit: impl FnOnce(&mut Player, &mut Vec<Playlist>)) {
+        let row = match self.player.hovered_row(&self.playlists) {
+            Some(r) => r,
+            None => return,
+        };
+        let name = match self.player.row_entry(row, &self.playlists) {
+            Some(e) => self.song_name(&e.id),
+            None => return,
+        };
+        let before = self.row_snapshot(row);
+        edit(&mut self.player, &mut self.playlists);
+        if let Some(before) = before {
+            self.record(format!("{} {}", action, name), vec![before]);
+        }
+    }
+
+    fn undo(&mut self) {
+        match self.undo_stack.undo(&mut self.player, &mut self.playlists) {
+            Ok(name) => {
+                self.status = format!("undid {}", name);
+                self.after_undo();
+            }
+            Err(e) => self.status = e,
+        }
+    }
+
+    fn redo(&mut self) {
+        match self.undo_stack.redo(&mut self.player, &mut self.playlists) {
+            Ok(name) => {
+                self.status = format!("redid {}", name);
+                self.after_undo();
+            }
+            Err(e) => self.status = e,
+        }
+    }
+
+    /// KEEPS THE CURSORS ON THINGS THAT STILL EXIST AFTER AN UNDO OR REDO
+    fn after_undo(&mut self) {
+        self.refresh_smart_playlists();
+        self.player.clamp_hover(&self.playlists);
+        self.playlist_i = self.playlist_i.min(self.playlists.len().saturating_sub(1));
+        let song_count = self.player.current_playlist.map_or(0, |i| self.playlists[i].songs.len());
+        self.playlist_song_i = self.playlist_song_i.min(song_count.saturating_sub(1));
+        self.grabbed_song = false;
+        self.playlists_changed = true;
+    }
+
+    /// OPENS THE TYPED IN BOX WITH THE SONGS TAGS OR NOTE ALREADY IN IT
+    fn edit_user_text(&mut self, input: ListInput) {
+        self.new_playlist_name = match &input {
+            ListInput::Tags(id) => self.user_data.tags(id).join(" "),
+            ListInput::Note(id) => self.user_data.note(id).to_string(),
+            _ => String::new(),
+        };
+        self.list_input = input;
+        self.is_adding_list = true;
+    }
+
+    /// WRITES THE PLAYLISTS, ERRORS GO TO THE STATUS INSTEAD OF CRASHING
+    pub fn save_playlists(&mut self) {
+        self.playlists_changed = false;
+        if !self.playlists_loaded {
+            return;
+        }
+        let path = self.glob_data.playlist_path.clone();
+
+        // backups are only rotated once a session, otherwise dragging a song around would flush them all out
+        if !self.backed_up {
+            if let Err(e) = serialize::rotate_backups(&path, PLAYLIST_BACKUPS) {
+                self.status = format!("couldn't back up playlists: {}", e);
+                return;
+            }
+            self.backed_up = true;
+        }
+        if let Err(e) = serialize_playlists(&self.playlists, &path) {
+            self.status = format!("couldn't save playlists: {}", e);
+        }
+    }
+
+    /// STOPS THE PLAYLISTS BEING SAVED THIS SESSION SINCE THE FILE COULDNT BE READ
+    pub fn playlists_unreadable(&mut self, error: io::Error) {
+        self.playlists_loaded = false;
+        self.status = format!("couldn't read playlists, they won't be saved: {}", error);
+    }
+
+    /// PUTS THE PLAYBAR BACK HOW IT WAS LEFT LAST TIME
+    pub fn restore_session(&mut self) {
+        if let Some(session) = load_session(&self.glob_data.session_path) {
+            session.restore(&mut self.player, &self.library, &mut self.playlists);
+        }
+    }
+
+    /// REMEMBERS THE PLAYBAR FOR NEXT TIME
+    pub fn save_session(&mut self) {
+        let session = Session::capture(&self.player, &self.playlists);
+        if let Err(e) = save_session(&session, &self.glob_data.session_path) {
+            self.status = format!("couldn't save session: {}", e);
+        }
+    }
+
+    /// WRITES THE SELECTED PLAYLIST TO THE EXPORT FOLDER FOR OTHER PLAYERS
+    fn export_selected_playlist(&mut self, format: PlaylistFormat) {
+        let playlist = match self.playlists.get(self.playlist_i) {
+            Some(p) => p,
+            None => return,
+        };
+        self.status = match export_playlist(playlist, &self.library, &self.glob_data.song_path, format, &self.glob_data.export_path) {
+            Ok(path) => format!("exported to {}", path.to_string_lossy()),
+            Err(e) => format!("export failed: {}", e),
+        };
+    }
+
+    /// ADDS A PLAYLIST FROM AN M3U OR XSPF FILE, LISTING ANY SONGS THAT COULDNT BE FOUND
+    fn import_playlist(&mut self, path: &str) {
+        let path = expand_home(path).to_string_lossy().to_string();
+        let (playlist, unmatched) = match import_playlist(&path, &self.library, &self.glob_data.song_path) {
+            Ok(p) => p,
+            Err(e) => {
+                self.status = format!("import failed: {}", e);
+                return;
+            }
+        };
+        self.status = match unmatched.is_empty() {
+            true => format!("imported {} songs into {}", playlist.songs.len(), playlist.name),
+            false => format!(
+                "imported {} songs into {}, {} not in library: {}", 
+                playlist.songs.len(), playlist.name, unmatched.len(), unmatched.join(", ")
+            ),
+        };
+        // the import status lists missing songs so its kept over the undo one
+        let before = vec![Snapshot::Playlist(self.playlists.len(), None)];
+        self.playlists.push(playlist);
+        let after = before.iter().map(|s| s.retake(&self.player, &self.playlists)).collect();
+        self.undo_stack.record(format!("imported {}", path), before, after);
+        self.playlists_changed = true;
+    }
+
+    /// SCANS THE SONG FOLDER AGAIN WITHOUT BLOCKING THE UI
+    fn rescan_library(&mut self) {
+        let song_path = self.glob_data.song_path.clone();
+        let sender = self.library_sender.clone();
+        let status_sender = self.status_sender.clone();
+        self.status = String::from("rescanning library...");
+        thread::spawn(move || {
+            match osu::load_all_songs(&song_path) {
+                Ok(songs) => { let _ = sender.send(songs); }
+                Err(e) => { let _ = status_sender.send(format!("couldn't rescan {}: {}", song_path, e)); }
+            }
+        });
+    }
+
+    /// THE SONG CURRENTLY IN THE SINK
+    pub fn playing_song(&self) -> Option<&Song> {
+        self.player.playing_entry().and_then(|e| self.library.get(&e.id))
+    }
+
+    /// THE SONG HOVERED IN THE SEARCH RESULTS
+    pub fn queried_song(&self) -> Option<&Song> {
+        self.queried_songs.get(self.query_i).map(|i| &self.library.songs()[*i])
+    }
+
+    /// EXPORTS THE HOVERED PLAYBAR SONG WITHOUT BLOCKING THE UI
+    /// it goes to the export folder unless another one is given
+    fn export_hovered_song(&mut self, format: ExportFormat, directory: Option<&str>) {
+        let entry = match self.player.hovered_entry(&self.playlists) {
+            Some(e) => e.clone(),
+            None => return,
+        };
+        let song = match self.library.get(&entry.id) {
+            Some(s) => s.clone(),
+            None => return,
+        };
+        let parent_path = self.glob_data.song_path.clone();
+        let directory = directory.map_or_else(|| self.glob_data.export_path.clone(), |d| expand_home(d).to_string_lossy().to_string());
+        let sender = self.status_sender.clone();
+
+        self.status = format!("exporting {}...", song.song_name);
+        thread::spawn(move || {
+            let message = match export_song(&song, &entry, &parent_path, format, &directory) {
+                Ok(path) => format!("exported to {}", path.to_string_lossy()),
+                Err(e) => format!("export failed: {}", e),
+            };
+            let _ = sender.send(message);
+        });
+    }
+
+    /// WHICH SET OF KEYS IS IN USE FOR THE PANEL THAT HAS FOCUS
+    pub fn key_section(&self) -> Section {
+        match self.current_ui {
+            UIMode::Input => Section::Search,
+            UIMode::PlayBar => Section::PlayBar,
+            UIMode::Playlist if self.player.current_playlist.is_some() => Section::PlaylistSongs,
+            UIMode::Playlist => Section::Playlists,
+        }
+    }
+
+    /// THE PANEL KEYS GO TO, THE SEARCH BOX STANDS IN FOR THE SONGS UNDER IT
+    pub fn focused_panel(&self) -> Panel {
+        match self.current_ui {
+            UIMode::Input => Panel::Search,
+            UIMode::PlayBar => Panel::PlayBar,
+            UIMode::Playlist => Panel::Playlists,
+        }
+    }
+
+    /// MOVES THE FOCUS ON, PAST ANY PANELS THAT ARE HIDDEN
+    fn focus_next_panel(&mut self) {
+        for _ in 0..3 {
+            self.current_ui = match self.current_ui {
+                UIMode::Input => UIMode::Playlist,
+                UIMode::Playlist => UIMode::PlayBar,
+                UIMode::PlayBar => UIMode::Input,
+            };
+            if !self.layout.is_hidden(self.focused_panel()) {
+                return;
+            }
+        }
+    }
+
+    /// HIDES A PANEL, THE FOCUS MOVES OFF IT BUT THE LAST ONE THAT CAN HAVE FOCUS STAYS
+    fn hide_panel(&mut self, panel: Panel) {
+        let mut layout = self.layout.clone();
+        layout.hide(panel);
+        if [Panel::Search, Panel::PlayBar, Panel::Playlists].iter().all(|p| layout.is_hidden(*p)) {
+            self.status = String::from("can't hide the last panel");
+            return;
+        }
+        self.layout = layout;
+        if self.layout.is_hidden(self.focused_panel()) {
+            self.focus_next_panel();
         }
     }
 
     /// HANDLES THE EVENTS WHICH MATTER REGARDLESS OF STATE
     pub fn event_handler(&mut self, key: &KeyEvent) -> Result<(), i32> {
+        // a confirmation prompt eats the next key whatever it is
+        if self.confirming.is_some() {
+            self.confirm_handler(key);
+            return Ok(())
+        }
+        if self.stats_period.is_some() {
+            self.stats_handler(key);
+            return Ok(())
+        }
+        if self.help_scroll.is_some() {
+            self.help_handler(key);
+            return Ok(())
+        }
+        // tags and notes can be typed from any panel so the box takes every key while its open
+        if self.is_adding_list {
+            self.playlist_name_handler(key);
+            return Ok(())
+        }
+        if self.command_line.is_open() {
+            return self.command_line_handler(key);
+        }
+
+        let action = self.keymap.action(self.key_section(), key);
+
         // handle the only bit that can error first
-        if let KeyCode::Esc = key.code {
-            if let UIMode::PlayBar = self.current_ui {
-                match self.player.playbar_source {
-                    PlaybarSource::Playlist => {},
-                    _ => return Err(1)
-                }
+        if let (Some(Action::Back), UIMode::PlayBar) = (action, self.current_ui) {
+            if self.player.playing_playlist.is_none() {
+                return Err(1)
             }
         }
 
-        // TAB is global thing which does the same thing
-        if let KeyCode::Tab = key.code {
-            match self.current_ui {
-                UIMode::Input => self.current_ui = UIMode::Playlist,
-                UIMode::Playlist => self.current_ui = UIMode::PlayBar,
-                UIMode::PlayBar => self.current_ui = UIMode::Input,
+        // the global keys do the same thing whichever panel has focus
+        match action {
+            Some(Action::NextPanel) => {
+                self.focus_next_panel();
+                return Ok(())
             }
-            return Ok(())
+            Some(Action::Maximize) => {
+                self.layout.maximized = !self.layout.maximized;
+                return Ok(())
+            }
+            Some(Action::HidePanel) => {
+                self.hide_panel(self.focused_panel());
+                return Ok(())
+            }
+            Some(Action::ShowPanels) => {
+                self.layout.show_all();
+                return Ok(())
+            }
+            // picks up songs added to the osu folder since starting
+            Some(Action::Rescan) => {
+                self.rescan_library();
+                return Ok(())
+            }
+            Some(Action::Stats) => {
+                self.stats_period = Some(Period::Week);
+                return Ok(())
+            }
+            Some(Action::Undo) => {
+                self.undo();
+                return Ok(())
+            }
+            Some(Action::Redo) => {
+                self.redo();
+                return Ok(())
+            }
+            Some(Action::Help) => {
+                self.help_scroll = Some(0);
+                return Ok(())
+            }
+            Some(Action::CommandLine) => {
+                self.command_line.open();
+                return Ok(())
+            }
+            _ => {}
         }
 
         // relegate input to the other functions
         match self.current_ui {
-            UIMode::Input => self.search_mode_handler(key),
-            UIMode::Playlist => self.playlist_handler(key),
-            UIMode::PlayBar => self.play_bar_handler(key)
+            UIMode::Input => self.search_mode_handler(key, action),
+            UIMode::Playlist => self.playlist_handler(action),
+            UIMode::PlayBar => self.play_bar_handler(action)
         }
 
-        return Ok(())
+        Ok(())
     }
 
     /// GETS ALL THE MATCHING SONGS FROM SEARCH QUERY
     pub fn get_matching_songs(&mut self) {
+        // while a field is half typed it's still searched as plain text
+        let query = Query::parse(&self.query).unwrap_or_else(|_| Query::text(&self.query));
+        let context = QueryContext::new(&self.stats, &self.user_data);
         self.queried_songs = Vec::new();
-        for song in &self.all_songs {
-            let new_name = song.song_name.to_lowercase();
-            if new_name.contains(&self.query.to_lowercase()) {
-                self.queried_songs.push(&song);
+        for (i, song) in self.library.songs().iter().enumerate() {
+            if query.matches(song, &context) {
+                self.queried_songs.push(i);
             }
         }
+        sort_songs(&mut self.queried_songs, self.sort, &self.library, &context);
+        if self.query_i >= self.queried_songs.len() {
+            self.query_i = 0;
+        }
     }
 
-    /// HANDLES INPUT FOR SONG QUERY BLOCK
-    fn search_mode_handler(&mut self, key: &KeyEvent) {
-        match key.code {
-            KeyCode::Esc => {
-                if self.query.len() != 0 {
+    /// HANDLES INPUT FOR SONG QUERY BLOCK, KEYS WITHOUT AN ACTION ARE TYPED
+    fn search_mode_handler(&mut self, key: &KeyEvent, action: Option<Action>) {
+        let action = match action {
+            Some(a) => a,
+            None => {
+                match key.code {
+                    // adds char to search query
+                    KeyCode::Char(e) => {
+                        self.query.push(e);
+                        self.get_matching_songs();
+                        self.query_i = 0;
+                    }
+                    // backspace on search query
+                    KeyCode::Backspace => {
+                        self.query.pop();
+                        self.get_matching_songs();
+                    }
+                    _ => {}
+                }
+                return;
+            }
+        };
+
+        match action {
+            Action::Back => {
+                if !self.query.is_empty() {
                     self.query = String::new();
                     self.get_matching_songs();
                     return;
@@ -106,201 +728,775 @@ impl<'a, 'b> App<'a, 'b> {
                 self.current_ui = UIMode::PlayBar;
             }
             // adds song to playlsit
-            KeyCode::Right => {
-                if self.playlists.len() == 0 {
+            Action::AddToPlaylist => {
+                if self.playlists.is_empty() {
                     return;
                 }
-                if self.queried_songs.len() == 0 {
+                if self.playlists[self.playlist_i].is_smart() {
+                    self.status = String::from("smart playlists fill themselves");
                     return;
                 }
-                let new_song = self.queried_songs[self.query_i];
-                self.playlists[self.playlist_i].songs.push(&new_song);
-                if self.player.current_playlist.is_some() {
-                    self.player.current_playlist = Some(&self.playlists[self.playlist_i]);
-                }
+                let new_song = match self.queried_song() {
+                    Some(s) => Entry::new(s.id.clone(), self.search_mod),
+                    None => return,
+                };
+                let before = vec![Snapshot::Songs(self.playlist_i, self.playlists[self.playlist_i].songs.clone())];
+                let name = format!("added {} to {}", self.song_name(&new_song.id), self.playlists[self.playlist_i].name);
+                self.playlists[self.playlist_i].songs.push(new_song);
+                self.record(name, before);
+                self.playlists_changed = true;
             }
-            // changes the mod of the song
-            KeyCode::Left => {
-                if self.queried_songs.len() == 0 {
-                    return;
-                }
-                let current_mod = self.queried_songs[self.query_i].modifier;
-                self.queried_songs[self.query_i].modifier = match current_mod {
-                    Mod::NoMod => Mod::DoubleTime,
-                    Mod::DoubleTime => Mod::Nightcore,
-                    Mod::Nightcore => Mod::NoMod,
-                }
+            // changes the mod songs are added with
+            Action::CycleSearchMod => self.search_mod = self.search_mod.next(),
+            // adds song to the end of the queue
+            Action::Enqueue => {
+                let new_song = match self.queried_song() {
+                    Some(s) => Entry::new(s.id.clone(), self.search_mod),
+                    None => return,
+                };
+                let before = vec![Snapshot::Queue(self.player.queue.clone())];
+                let name = format!("queued {}", self.song_name(&new_song.id));
+                self.player.enqueue(new_song);
+                self.record(name, before);
             }
-            // adds song to playbar
-            KeyCode::Enter => {
-                if self.queried_songs.len() == 0 {
-                    return;
+            // changes what the results are ordered by
+            Action::CycleSort => {
+                self.sort = self.sort.next();
+                self.get_matching_songs();
+            }
+            // puts the song at the front of the queue instead
+            Action::PlayNext => {
+                if let Some(s) = self.queried_song() {
+                    let new_song = Entry::new(s.id.clone(), self.search_mod);
+                    let before = vec![Snapshot::Queue(self.player.queue.clone())];
+                    let name = format!("queued {} next", s.song_name);
+                    self.player.play_next(new_song);
+                    self.record(name, before);
                 }
-                let new_song = self.queried_songs[self.query_i].clone();
-                self.player.add_normal_song(&new_song);
             }
-            // adds char to search query
-            KeyCode::Char(e) => {
-                self.query.push(e);
-                self.get_matching_songs();
-                self.query_i = 0;
+            Action::Favourite => {
+                if let Some(id) = self.queried_song().map(|s| s.id.clone()) {
+                    self.toggle_favourite(&id);
+                }
             }
-            // backspace on search query
-            KeyCode::Backspace => {
-                self.query.pop();
-                self.get_matching_songs();
+            Action::Rate(rating) => {
+                if let Some(id) = self.queried_song().map(|s| s.id.clone()) {
+                    self.set_rating(&id, rating);
+                }
             }
-            // move down results
-            KeyCode::Down => {
-                if self.query_i != self.queried_songs.len() - 1{
-                    self.query_i += 1;
-                    return;
+            Action::EditTags => {
+                if let Some(id) = self.queried_song().map(|s| s.id.clone()) {
+                    self.edit_user_text(ListInput::Tags(id));
                 }
-                self.query_i = 0;
             }
-            // move up results
-            KeyCode::Up => {
-                if self.query_i != 0 {
-                    self.query_i -= 1;
-                    return;
+            Action::EditNote => {
+                if let Some(id) = self.queried_song().map(|s| s.id.clone()) {
+                    self.edit_user_text(ListInput::Note(id));
                 }
-                self.query_i = self.queried_songs.len() - 1;
             }
+            // move up and down the results
+            Action::Up => self.move_search_cursor(true),
+            Action::Down => self.move_search_cursor(false),
             _ => {}
         }
     }
 
+    /// MOVES THROUGH THE SEARCH RESULTS, WRAPPING ROUND AT EITHER END
+    fn move_search_cursor(&mut self, up: bool) {
+        if self.queried_songs.is_empty() {
+            return;
+        }
+        let last = self.queried_songs.len() - 1;
+        self.query_i = match (up, self.query_i) {
+            (true, 0) => last,
+            (true, i) => i - 1,
+            (false, i) if i == last => 0,
+            (false, i) => i + 1,
+        };
+    }
+
     /// HANDLES INPUT FOR NOW PLAYING BLOCK
-    fn play_bar_handler(&mut self, key: &KeyEvent) {
-        match key.code {
-            KeyCode::Esc => {
+    fn play_bar_handler(&mut self, action: Option<Action>) {
+        let action = match action {
+            Some(a) => a,
+            None => return,
+        };
+        match action {
+            Action::Back => {
                 // if it makes it here, we know user wants to unload playlist
                 self.player.unload_playbar_playlist(); 
             }
             // plays song currently being hovered
-            KeyCode::Enter => self.player.force_new_song(),
+            Action::Play => self.player.force_new_song(&self.library, &mut self.playlists),
+            // restarts the song or goes back through what has played
+            Action::Previous => self.player.previous(&self.library),
+            Action::Skip => self.player.skip(),
+            Action::RepeatOne => self.player.toggle_repeat_one(),
+            // switches between up next and what has already played
+            Action::ToggleHistory => self.player.toggle_view(),
+            Action::ClearQueue => {
+                let before = vec![
+                    Snapshot::Queue(self.player.queue.clone()),
+                    Snapshot::PlayingPlaylist(self.player.playing_playlist),
+                ];
+                self.player.clear_queue();
+                self.record(String::from("cleared the queue"), before);
+            }
             // pause / unpause song
-            KeyCode::Char(' ') => {
+            Action::Pause => {
                 if self.player.sink.is_paused() {
                     self.player.sink.play();
                     return;
                 }
                 self.player.sink.pause();
             }
-            // remove hovered song from playbar
-            KeyCode::Delete => self.player.remove_hovered_song(),
-            // move hover up playbar
-            KeyCode::Up => {
-                if self.player.current_songs.is_empty() {
-                    return;
+            // cycles through the click tracks mixed into the song
+            Action::ClickTrack => self.player.cycle_click_mode(&self.library),
+            // renders the hovered song to a file with its mod
+            Action::ExportWav => self.export_hovered_song(ExportFormat::Wav, None),
+            Action::ExportFlac => self.export_hovered_song(ExportFormat::Flac, None),
+            // cycles through the visualizer panels
+            Action::Visualizer => self.visualizer = self.visualizer.next(),
+            // favourites or rates the hovered song
+            Action::Favourite => {
+                if let Some(id) = self.player.hovered_entry(&self.playlists).map(|e| e.id.clone()) {
+                    self.toggle_favourite(&id);
                 }
-                if self.player.hovered_index == 0 {
-                    self.player.hovered_index = self.player.current_songs.len() - 1;
-                    return;
+            }
+            Action::Rate(rating) => {
+                if let Some(id) = self.player.hovered_entry(&self.playlists).map(|e| e.id.clone()) {
+                    self.set_rating(&id, rating);
                 }
-                self.player.hovered_index -= 1;
             }
-            // move hover down playbar
-            KeyCode::Down => {
-                if self.player.current_songs.is_empty() {
-                    return;
+            Action::EditTags => {
+                if let Some(id) = self.player.hovered_entry(&self.playlists).map(|e| e.id.clone()) {
+                    self.edit_user_text(ListInput::Tags(id));
                 }
-                self.player.hovered_index += 1;
-                if self.player.hovered_index == self.player.current_songs.len() {
-                    self.player.hovered_index = 0;
+            }
+            Action::EditNote => {
+                if let Some(id) = self.player.hovered_entry(&self.playlists).map(|e| e.id.clone()) {
+                    self.edit_user_text(ListInput::Note(id));
                 }
             }
+            // changes the mod or rate of the hovered song
+            Action::CycleMod => self.edit_hovered_row("changed the mod of", |p, l| p.cycle_hovered_mod(l)),
+            Action::Faster => self.edit_hovered_row("changed the rate of", |p, l| p.change_hovered_rate(l, RATE_STEP)),
+            Action::Slower => self.edit_hovered_row("changed the rate of", |p, l| p.change_hovered_rate(l, -RATE_STEP)),
+            // remove hovered song from playbar, removing the playing one is just a skip so it isnt undone
+            Action::Remove => match self.player.hovered_row(&self.playlists) {
+                Some(QueueRow::Playing) => self.player.remove_hovered_song(&mut self.playlists),
+                _ => self.edit_hovered_row("removed", |p, l| p.remove_hovered_song(l)),
+            },
+            // move hover up and down the playbar
+            Action::Up => self.player.move_hover(&self.playlists, true),
+            Action::Down => self.player.move_hover(&self.playlists, false),
             _ => {}
         }
     }
 
     /// HANDLES INPUT FOR PLAYLISTS
-    fn playlist_handler(&mut self, key: &KeyEvent) {
-        match key.code {
-            KeyCode::Esc => {
+    fn playlist_handler(&mut self, action: Option<Action>) {
+        let action = match action {
+            Some(a) => a,
+            None => return,
+        };
+        if self.player.current_playlist.is_some() {
+            self.inner_playlist_handler(action);
+            return;
+        }
+
+        match action {
+            Action::Back => {
                 // makes sense to have two ways to remove playlist
-                if let PlaybarSource::Playlist = self.player.playbar_source {
+                if self.player.playing_playlist.is_some() {
                     self.player.unload_playbar_playlist();
                     return;
                 }
-                if self.player.current_playlist.is_some() {
-                    self.player.pop_current_playlist();
-                    return;
-                }
                 self.current_ui = UIMode::PlayBar;
             }
-            // unloads the playlsit
-            KeyCode::Left => {
-                if self.player.current_playlist.is_some() {
-                    self.playlists[self.playlist_i] = *self.player.pop_current_playlist();
+            Action::NewPlaylist => self.is_adding_list = true,
+            Action::Save => self.save_playlists(),
+            Action::Rename => {
+                if let Some(p) = self.playlists.get(self.playlist_i) {
+                    self.new_playlist_name = p.name.clone();
+                    self.list_input = ListInput::Rename(self.playlist_i);
+                    self.is_adding_list = true;
+                }
+            }
+            Action::Duplicate => {
+                if let Some(p) = self.playlists.get(self.playlist_i) {
+                    let copy = p.duplicate();
+                    let name = format!("copied {}", p.name);
+                    let before = vec![Snapshot::Playlist(self.playlist_i + 1, None)];
+                    self.playlists.insert(self.playlist_i + 1, copy);
+                    self.player.playlist_inserted(self.playlist_i + 1);
+                    self.record(name, before);
+                    self.playlists_changed = true;
+                }
+            }
+            // queues every song after whatever is already queued
+            Action::QueuePlaylist => {
+                if let Some(p) = self.playlists.get(self.playlist_i) {
+                    let before = vec![Snapshot::Queue(self.player.queue.clone())];
+                    let name = format!("queued {}", p.name);
+                    self.player.enqueue_playlist(p);
+                    self.record(name, before);
+                }
+            }
+            // the typed in box is reused for the path to import from
+            Action::Import => {
+                self.list_input = ListInput::Import;
+                self.is_adding_list = true;
+            }
+            // smart playlists start off with whatever is in the search box
+            Action::NewSmartPlaylist => {
+                self.new_playlist_name = self.query.clone();
+                self.list_input = ListInput::Smart;
+                self.is_adding_list = true;
+            }
+            Action::EditQuery => {
+                if let Some(query) = self.playlists.get(self.playlist_i).and_then(|p| p.query.clone()) {
+                    self.new_playlist_name = query;
+                    self.list_input = ListInput::EditQuery(self.playlist_i);
+                    self.is_adding_list = true;
                 }
             }
-            // removes char from new playlist
+            Action::ExportM3u => self.export_selected_playlist(PlaylistFormat::M3u),
+            Action::ExportXspf => self.export_selected_playlist(PlaylistFormat::Xspf),
+            Action::Remove if self.playlist_i < self.playlists.len() => {
+                self.confirming = Some(ConfirmAction::DeletePlaylist(self.playlist_i));
+            }
+            // opens the playlist to show its songs
+            Action::Open if !self.playlists.is_empty() => {
+                self.player.load_playlist(self.playlist_i);
+                self.playlist_song_i = 0;
+            }
+            Action::Up => self.move_playlist_selection(true),
+            Action::Down => self.move_playlist_selection(false),
+            _ => {}
+        }
+    }
+
+    /// MOVES THROUGH THE LIST OF PLAYLISTS, WRAPPING ROUND AT EITHER END
+    fn move_playlist_selection(&mut self, up: bool) {
+        if self.playlists.is_empty() {
+            return;
+        }
+        let last = self.playlists.len() - 1;
+        self.playlist_i = match (up, self.playlist_i) {
+            (true, 0) => last,
+            (true, i) => i - 1,
+            (false, i) if i == last => 0,
+            (false, i) => i + 1,
+        };
+    }
+
+    /// HANDLES TYPING THE NAME OF A NEW OR RENAMED PLAYLIST, A FILE TO IMPORT, OR A SONGS TAGS AND NOTE
+    fn playlist_name_handler(&mut self, key: &KeyEvent) {
+        match key.code {
+            KeyCode::Esc => {
+                self.new_playlist_name = String::new();
+                self.list_input = ListInput::New;
+                self.is_adding_list = false;
+            }
             KeyCode::Backspace => {
                 self.new_playlist_name.pop();
             }
-            KeyCode::Char(c) => {
-                // checks if char is for new name
-                if self.is_adding_list {
-                    self.new_playlist_name.push(c);
+            KeyCode::Char(c) => self.new_playlist_name.push(c),
+            KeyCode::Enter => {
+                // cant make a new list without a name, but an empty note or tag list clears it
+                let clears = matches!(self.list_input, ListInput::Tags(_) | ListInput::Note(_));
+                if self.new_playlist_name.is_empty() && !clears {
                     return;
                 }
-                // matches it to shortcuts
-                match c {
-                    'n' => self.is_adding_list = true,
-                    'q' => serialize_playlists(&self.playlists, &self.glob_data.playlist_path.clone()),
-                    'w' => {
-                        // change the playlist stored in the player and return it to the vec when finished.
-                        if let Some(mut p) = self.player.current_playlist.clone() {
-                            p.toggle_repeat();
-                            self.player.current_playlist = Some(p);
-                        }
-                    }
-                    'e' => {
-                        if let Some(mut p) = self.player.current_playlist.clone() {
-                            p.toggle_shuffle();
-                            self.player.current_playlist = Some(p);
-                        }
-                    },
-                    _ => {}
+
+                // a bad query keeps the box open so it can be fixed
+                if let Err(e) = self.submit_list_input() {
+                    self.status = e;
+                    return;
+                }
+                self.new_playlist_name = String::new();
+                self.list_input = ListInput::New;
+                self.is_adding_list = false;
+            }
+            _ => {}
+        }
+    }
+
+    /// HANDLES TYPING A : COMMAND, ONLY QUITTING CAN ERROR
+    fn command_line_handler(&mut self, key: &KeyEvent) -> Result<(), i32> {
+        match key.code {
+            KeyCode::Esc => self.command_line.close(),
+            KeyCode::Backspace => self.command_line.pop(),
+            KeyCode::Up => self.command_line.move_history(true),
+            KeyCode::Down => self.command_line.move_history(false),
+            KeyCode::Tab => {
+                let names: Vec<&str> = self.playlists.iter().map(|p| p.name.as_str()).collect();
+                if self.command_line.complete(&names) == 0 {
+                    self.status = String::from("nothing to complete");
                 }
             }
+            KeyCode::Char(c) => self.command_line.push(c),
             KeyCode::Enter => {
-                // loads current playlist into now playing bar
-                if self.player.current_playlist.is_some() {
-                    self.player.load_playbar_playlist();
+                let line = match self.command_line.submit() {
+                    Some(l) => l,
+                    None => return Ok(()),
+                };
+                let result = Command::parse(&line).and_then(|command| match command {
+                    // quitting has to get back to main so it isnt run like the rest
+                    Command::Quit => Ok(true),
+                    command => self.run_command(command).map(|_| false),
+                });
+                self.save_command_history();
+                match result {
+                    Ok(true) => return Err(1),
+                    Ok(false) => {}
+                    Err(e) => self.status = e,
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn save_command_history(&mut self) {
+        if let Err(e) = serialize::serialize(&self.command_line.history(), &self.glob_data.command_history_path) {
+            self.status = format!("couldn't save command history: {}", e);
+        }
+    }
+
+    /// THE PLAYLIST WITH THE NAME, CASE ONLY MATTERS IF TWO NAMES DIFFER BY IT
+    fn playlist_named(&self, name: &str) -> Result<usize, String> {
+        self.playlists.iter().position(|p| p.name == name)
+            .or_else(|| self.playlists.iter().position(|p| p.name.eq_ignore_ascii_case(name)))
+            .ok_or_else(|| format!("no playlist called {}", name))
+    }
+
+    /// DOES A TYPED COMMAND, WHAT IT DID GOES TO THE STATUS
+    fn run_command(&mut self, command: Command) -> Result<(), String> {
+        match command {
+            Command::NewPlaylist(name) => {
+                let before = vec![Snapshot::Playlist(self.playlists.len(), None)];
+                self.playlists.push(Playlist::new_empty(name.clone()));
+                self.record(format!("made {}", name), before);
+                self.playlists_changed = true;
+            }
+            Command::RenamePlaylist(name, new_name) => {
+                let i = self.playlist_named(&name)?;
+                let before = vec![Snapshot::Name(i, self.playlists[i].name.clone())];
+                self.playlists[i].name = new_name.clone();
+                self.record(format!("renamed {} to {}", name, new_name), before);
+                self.playlists_changed = true;
+            }
+            // goes through the same y/n as the delete key
+            Command::DeletePlaylist(name) => self.confirming = Some(ConfirmAction::DeletePlaylist(self.playlist_named(&name)?)),
+            Command::Export(format, name, path) => {
+                let playlist = &self.playlists[self.playlist_named(&name)?];
+                let parent_path = &self.glob_data.song_path;
+                let written = match path {
+                    Some(path) => {
+                        let path = expand_home(&path);
+                        write_playlist(playlist, &self.library, parent_path, format, &path).map(|_| path)
+                    }
+                    None => export_playlist(playlist, &self.library, parent_path, format, &self.glob_data.export_path),
+                };
+                self.status = match written {
+                    Ok(path) => format!("exported to {}", path.to_string_lossy()),
+                    Err(e) => format!("export failed: {}", e),
+                };
+            }
+            Command::ExportSong(format, directory) => {
+                if self.player.hovered_entry(&self.playlists).is_none() {
+                    return Err(String::from("hover a song in the playbar to export it"));
+                }
+                self.export_hovered_song(format, directory.as_deref());
+            }
+            Command::Import(path) => self.import_playlist(&path),
+            Command::Rescan => self.rescan_library(),
+            Command::Volume(volume) => {
+                self.player.set_volume(volume as f32 / 100.0);
+                self.status = format!("volume {}%", volume);
+            }
+            Command::Sort(key) => {
+                self.sort = key;
+                self.get_matching_songs();
+                self.status = format!("sorting by {}", key.name());
+            }
+            Command::Mod(modifier) => {
+                self.search_mod = modifier;
+                self.status = format!("songs are added as {}", modifier.short_name());
+            }
+            // only for this session, the theme file is what sticks
+            Command::Theme(name) => {
+                self.theme = Theme::named(&name).ok_or_else(|| format!("no theme called {}", name))?;
+                self.status = format!("using the {} theme", self.theme.name);
+            }
+            Command::Hide(panel) => self.hide_panel(panel),
+            Command::Show(Some(panel)) => self.layout.show(panel),
+            Command::Show(None) => self.layout.show_all(),
+            Command::Arrange(arrangement) => {
+                self.layout.arrangement = arrangement;
+                self.status = format!("layout is {}", arrangement.name());
+            }
+            Command::Save => {
+                self.status = String::from("saved playlists");
+                self.save_playlists();
+            }
+            Command::Quit => {}
+        }
+        Ok(())
+    }
+
+    /// DOES WHATEVER THE TYPED TEXT WAS FOR
+    fn submit_list_input(&mut self) -> Result<(), String> {
+        let text = self.new_playlist_name.clone();
+        match self.list_input.clone() {
+            ListInput::Tags(id) => {
+                self.user_data.set_tags(&id, parse_tags(&text));
+                self.user_data_edited();
+                return Ok(());
+            }
+            ListInput::Note(id) => {
+                self.user_data.set_note(&id, text);
+                self.user_data_edited();
+                return Ok(());
+            }
+            ListInput::Rename(i) => {
+                let before = vec![Snapshot::Name(i, self.playlists[i].name.clone())];
+                let name = format!("renamed {} to {}", self.playlists[i].name, text);
+                self.playlists[i].name = text;
+                self.record(name, before);
+            }
+            ListInput::New => {
+                let before = vec![Snapshot::Playlist(self.playlists.len(), None)];
+                self.playlists.push(Playlist::new_empty(text.clone()));
+                self.record(format!("made {}", text), before);
+            }
+            ListInput::Import => self.import_playlist(&text),
+            ListInput::Smart => {
+                let context = QueryContext::new(&self.stats, &self.user_data);
+                let playlist = Playlist::new_smart(text.clone(), text.clone(), &self.library, &context)?;
+                let before = vec![Snapshot::Playlist(self.playlists.len(), None)];
+                self.playlists.push(playlist);
+                self.record(format!("made smart playlist {}", text), before);
+            }
+            ListInput::EditQuery(i) => {
+                Query::parse(&text)?;
+                let before = vec![Snapshot::Query(i, self.playlists[i].query.clone())];
+                self.playlists[i].query = Some(text);
+                self.playlists[i].refresh(&self.library, &QueryContext::new(&self.stats, &self.user_data));
+                self.record(format!("changed the query of {}", self.playlists[i].name), before);
+            }
+        }
+        self.playlists_changed = true;
+        Ok(())
+    }
+
+    /// HANDLES INPUT WHEN LOOKING AT THE SONGS INSIDE A PLAYLIST
+    fn inner_playlist_handler(&mut self, action: Action) {
+        let index = self.player.current_playlist.unwrap();
+        let song_count = self.playlists[index].songs.len();
+        // smart playlists are in library order and cant be edited by hand
+        let editable = !self.playlists[index].is_smart();
+
+        match action {
+            Action::Back => {
+                if self.grabbed_song {
+                    self.grabbed_song = false;
                     return;
                 }
-                // loads song to player
-                if !self.is_adding_list && self.playlists.len() != 0{
-                    self.player.load_playlist(&self.playlists[self.playlist_i]);
+                // makes sense to have two ways to remove playlist
+                if self.player.playing_playlist.is_some() {
+                    self.player.unload_playbar_playlist();
+                    return;
                 }
-                // cant make a new list without a name
-                if self.new_playlist_name.len() == 0 {
+                self.player.close_playlist();
+            }
+            // unloads the playlsit
+            Action::Close => {
+                self.grabbed_song = false;
+                self.player.close_playlist();
+            }
+            Action::PlayPlaylist => {
+                // dropping a grabbed song shouldnt start playing
+                if self.grabbed_song {
+                    self.grabbed_song = false;
                     return;
                 }
+                // loads current playlist into now playing bar
+                self.player.load_playbar_playlist(&self.library, &mut self.playlists);
+            }
+            Action::Save => self.save_playlists(),
+            Action::QueuePlaylist => {
+                let before = vec![Snapshot::Queue(self.player.queue.clone())];
+                self.player.enqueue_playlist(&self.playlists[index]);
+                self.record(format!("queued {}", self.playlists[index].name), before);
+            }
+            Action::ToggleRepeat => self.playlists[index].toggle_repeat(),
+            Action::ToggleShuffle => self.playlists[index].toggle_shuffle(),
+            // picks the song up so up and down drag it around
+            Action::Grab => self.grabbed_song = !self.grabbed_song && song_count != 0 && editable,
+            Action::Faster | Action::Slower if editable && self.playlist_song_i < song_count => {
+                let step = if action == Action::Faster { RATE_STEP } else { -RATE_STEP };
+                self.edit_playlist_song(index, "changed the rate of", |e| e.change_rate(step));
+            }
+            Action::Remove if editable && self.playlist_song_i < song_count => {
+                self.confirming = Some(ConfirmAction::RemoveSong(index, self.playlist_song_i));
+            }
+            // changes the mod of the selected song
+            Action::CycleMod if editable && self.playlist_song_i < song_count => {
+                self.edit_playlist_song(index, "changed the mod of", Entry::cycle_mod);
+            }
+            // a grabbed song is dragged by up and down, the drag keys move it without grabbing
+            Action::Up => self.move_playlist_cursor(index, true, editable && self.grabbed_song),
+            Action::Down => self.move_playlist_cursor(index, false, editable && self.grabbed_song),
+            Action::DragUp => self.move_playlist_cursor(index, true, editable),
+            Action::DragDown => self.move_playlist_cursor(index, false, editable),
+            _ => {}
+        }
+    }
 
-                // creates the playlist
-                let new_playlist = Playlist::new_empty(self.new_playlist_name.clone());
-                self.playlists.push(new_playlist);
-                self.new_playlist_name = String::new();
-                self.is_adding_list = false;
+    /// MOVES THE CURSOR IN AN OPEN PLAYLIST, TAKING THE SONG WITH IT IF ITS BEING DRAGGED
+    fn move_playlist_cursor(&mut self, index: usize, up: bool, dragging: bool) {
+        let song_count = self.playlists[index].songs.len();
+        if song_count == 0 {
+            return;
+        }
+        let new_i = match (up, self.playlist_song_i) {
+            (true, 0) => song_count - 1,
+            (true, i) => i - 1,
+            (false, i) => (i + 1) % song_count,
+        };
+        if dragging {
+            self.move_playlist_song(index, new_i);
+        }
+        self.playlist_song_i = new_i;
+    }
+
+    /// CHANGES THE SELECTED SONG OF AN OPEN PLAYLIST SO THAT IT CAN BE UNDONE
+    fn edit_playlist_song(&mut self, index: usize, action: &str, edit: impl FnOnce(&mut Entry)) {
+        let before = vec![Snapshot::Songs(index, self.playlists[index].songs.clone())];
+        let entry = &mut self.playlists[index].songs[self.playlist_song_i];
+        let name = format!("{} {}", action, self.library.get(&entry.id).map_or(entry.id.as_str(), |s| s.song_name.as_str()));
+        edit(entry);
+        self.record(name, before);
+        self.playlists_changed = true;
+    }
+
+    fn move_playlist_song(&mut self, index: usize, new_i: usize) {
+        let before = vec![Snapshot::Songs(index, self.playlists[index].songs.clone())];
+        let name = format!("moved {}", self.song_name(&self.playlists[index].songs[self.playlist_song_i].id));
+        self.playlists[index].move_song(self.playlist_song_i, new_i);
+        self.record(name, before);
+        self.playlists_changed = true;
+    }
+
+    /// HANDLES THE STATS POPUP, LEFT AND RIGHT CHANGE HOW FAR BACK IT LOOKS
+    fn stats_handler(&mut self, key: &KeyEvent) {
+        let period = match self.stats_period {
+            Some(p) => p,
+            None => return,
+        };
+        self.stats_period = match key.code {
+            KeyCode::Left => Some(period.previous()),
+            KeyCode::Right => Some(period.next()),
+            _ if self.keymap.action(Section::Global, key) == Some(Action::Stats) => None,
+            KeyCode::Esc => None,
+            _ => Some(period),
+        };
+    }
+
+    /// HANDLES CLICKS AND THE SCROLL WHEEL, MATCHED TO WHERE THE PANELS WERE LAST DRAWN
+    pub fn mouse_handler(&mut self, mouse: &MouseEvent) {
+        // popups and the typing box keep hold of the focus until theyre closed
+        if self.confirming.is_some() || self.stats_period.is_some() || self.is_adding_list || self.command_line.is_open() {
+            return;
+        }
+        if let Some(scroll) = self.help_scroll {
+            self.help_scroll = match mouse.kind {
+                MouseEventKind::ScrollUp => Some(scroll.saturating_sub(1)),
+                MouseEventKind::ScrollDown => Some((scroll + 1).min(self.max_help_scroll())),
+                _ => Some(scroll),
+            };
+            return;
+        }
+
+        let (panel, area) = match self.areas.panel_at(mouse.column, mouse.row) {
+            Some(p) => p,
+            None => return,
+        };
+        match mouse.kind {
+            MouseEventKind::ScrollUp => self.scroll_panel(panel, true),
+            MouseEventKind::ScrollDown => self.scroll_panel(panel, false),
+            MouseEventKind::Down(MouseButton::Left) => {
+                let double = self.last_click
+                    .is_some_and(|(at, p, y)| at.elapsed() < DOUBLE_CLICK && p == panel && y == mouse.row);
+                // a third click starts a new double click rather than counting as another one
+                self.last_click = match double {
+                    true => None,
+                    false => Some((Instant::now(), panel, mouse.row)),
+                };
+                self.click_panel(panel, area, mouse.column, mouse.row, double);
+            }
+            _ => {}
+        }
+    }
+
+    /// THE SCROLL WHEEL MOVES THE SELECTION OF THE LIST UNDER IT
+    fn scroll_panel(&mut self, panel: Panel, up: bool) {
+        match panel {
+            Panel::Songs => self.move_search_cursor(up),
+            Panel::PlayBar => self.player.move_hover(&self.playlists, up),
+            Panel::Playlists => match self.player.current_playlist {
+                Some(index) => self.move_playlist_cursor(index, up, false),
+                None => self.move_playlist_selection(up),
+            },
+            _ => {}
+        }
+    }
+
+    /// FOCUSES THE CLICKED PANEL AND SELECTS THE LINE, A DOUBLE CLICK PLAYS IT
+    fn click_panel(&mut self, panel: Panel, area: Rect, x: u16, y: u16, double: bool) {
+        match panel {
+            Panel::Search => self.current_ui = UIMode::Input,
+            Panel::Songs => {
+                self.current_ui = UIMode::Input;
+                let index = match list_index(area, y, self.query_i, self.queried_songs.len()) {
+                    Some(i) => i,
+                    None => return,
+                };
+                self.query_i = index;
+                // the song goes to the front of the queue and whatever is on is cut off
+                if double {
+                    if let Some(s) = self.queried_song() {
+                        let entry = Entry::new(s.id.clone(), self.search_mod);
+                        self.player.play_next(entry);
+                        self.player.skip();
+                    }
+                }
+            }
+            Panel::PlayBar => {
+                self.current_ui = UIMode::PlayBar;
+                let count = self.player.rows(&self.playlists).len();
+                if let Some(i) = list_index(area, y, self.player.hovered_index, count) {
+                    self.player.hovered_index = i;
+                    if double {
+                        self.player.force_new_song(&self.library, &mut self.playlists);
+                    }
+                }
+            }
+            Panel::Progress => self.seek_to(area, x),
+            Panel::Info | Panel::Visualizer => {}
+            Panel::Playlists => {
+                self.current_ui = UIMode::Playlist;
+                self.grabbed_song = false;
+                match self.player.current_playlist {
+                    Some(index) => {
+                        let count = self.playlists[index].songs.len();
+                        if let Some(i) = list_index(area, y, self.playlist_song_i, count) {
+                            self.playlist_song_i = i;
+                            if double {
+                                self.player.load_playbar_playlist_from(&self.library, &mut self.playlists, Some(i));
+                            }
+                        }
+                    }
+                    None => {
+                        if let Some(i) = list_index(area, y, self.playlist_i, self.playlists.len()) {
+                            self.playlist_i = i;
+                            if double {
+                                self.player.load_playlist(i);
+                                self.playlist_song_i = 0;
+                            }
+                        }
+                    }
+                }
             }
-            KeyCode::Up => {
-                if self.playlist_i == 0 {
-                    self.playlist_i = self.playlists.len() - 1;
-                } else {
+        }
+    }
+
+    /// SEEKS TO WHERE THE PROGRESS BAR WAS CLICKED, EACH CELL IS THE SAME SHARE OF THE SONG
+    fn seek_to(&mut self, area: Rect, x: u16) {
+        let width = area.width.saturating_sub(2);
+        if width == 0 || x <= area.x || x > area.x + width {
+            return;
+        }
+        let length = match self.playing_song() {
+            Some(s) => s.length as f64,
+            None => return,
+        };
+        let fraction = (x - area.x - 1) as f64 / width as f64;
+        self.player.seek(&self.library, Duration::from_secs_f64(fraction * length));
+    }
+
+    /// HOW FAR DOWN THE HELP GOES BEFORE ITS LAST LINE IS AT THE BOTTOM OF THE POPUP
+    fn max_help_scroll(&self) -> usize {
+        let section = self.key_section();
+        // search has a line saying everything else is typed
+        let lines = self.keymap.bindings(section).len() + usize::from(section == Section::Search);
+        let shown = self.areas.help.height.saturating_sub(2) as usize;
+        lines.saturating_sub(shown)
+    }
+
+    /// HANDLES THE HELP POPUP, THE PANELS OWN UP AND DOWN KEYS SCROLL IT
+    fn help_handler(&mut self, key: &KeyEvent) {
+        let scroll = match self.help_scroll {
+            Some(s) => s,
+            None => return,
+        };
+        self.help_scroll = match self.keymap.action(self.key_section(), key) {
+            Some(Action::Up) => Some(scroll.saturating_sub(1)),
+            Some(Action::Down) => Some((scroll + 1).min(self.max_help_scroll())),
+            Some(Action::Help) | Some(Action::Back) => None,
+            _ if key.code == KeyCode::Esc => None,
+            _ => Some(scroll),
+        };
+    }
+
+    /// HANDLES THE YES OR NO OF A CONFIRMATION PROMPT
+    fn confirm_handler(&mut self, key: &KeyEvent) {
+        let action = match self.confirming.take() {
+            Some(a) => a,
+            None => return,
+        };
+        if !matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+            return;
+        }
+
+        self.playlists_changed = true;
+        match action {
+            ConfirmAction::DeletePlaylist(i) => {
+                let name = format!("deleted {}", self.playlists[i].name);
+                let before = vec![Snapshot::Playlist(i, Some(Box::new(self.playlists[i].clone())))];
+                self.playlists.remove(i);
+                self.player.playlist_removed(i);
+                self.record_edit(name, before, vec![Snapshot::Playlist(i, None)]);
+                if self.playlist_i >= self.playlists.len() && self.playlist_i != 0 {
                     self.playlist_i -= 1;
                 }
             }
-            KeyCode::Down => {
-                if self.playlist_i == self.playlists.len() - 1 {
-                    self.playlist_i = 0;
-                } else {
-                    self.playlist_i += 1;
+            ConfirmAction::RemoveSong(playlist, song) => {
+                let before = vec![Snapshot::Songs(playlist, self.playlists[playlist].songs.clone())];
+                let name = format!("removed {} from {}", self.song_name(&self.playlists[playlist].songs[song].id), self.playlists[playlist].name);
+                self.playlists[playlist].songs.remove(song);
+                self.record(name, before);
+                let song_count = self.playlists[playlist].songs.len();
+                if self.playlist_song_i >= song_count && self.playlist_song_i != 0 {
+                    self.playlist_song_i -= 1;
                 }
             }
-            _ => {}
         }
     }
-}
\ No newline at end of file
+
+    /// THE QUESTION SHOWN FOR THE ACTION WAITING TO BE CONFIRMED
+    pub fn confirm_message(&self) -> Option<String> {
+        let message = match self.confirming.as_ref()? {
+            ConfirmAction::DeletePlaylist(i) => format!("delete playlist \"{}\"?", self.playlists[*i].name),
+            ConfirmAction::RemoveSong(p, s) => {
+                let id = &self.playlists[*p].songs[*s].id;
+                let name = self.library.get(id).map_or(id.as_str(), |s| s.song_name.as_str());
+                format!("remove \"{}\" from \"{}\"?", name, self.playlists[*p].name)
+            }
+        };
+        Some(format!("{} (y/n)", message))
+    }
+}
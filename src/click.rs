@@ -0,0 +1,205 @@
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+
+use libosu::prelude::{Beatmap, SampleSet};
+use rodio::{Decoder, Source};
+use rodio::source::UniformSourceIterator;
+
+use crate::osu::Song;
+
+/// the click track is generated mono at this rate, the mixer converts it to match the song
+const CLICK_RATE: u32 = 44100;
+/// how loud the clicks are compared to the song
+const CLICK_GAIN: f32 = 0.5;
+
+/// what gets mixed into the song for practice
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ClickMode {
+    Off,
+    Metronome,
+    Hitsounds,
+    Both,
+}
+
+impl ClickMode {
+    pub fn next(self) -> Self {
+        match self {
+            ClickMode::Off => ClickMode::Metronome,
+            ClickMode::Metronome => ClickMode::Hitsounds,
+            ClickMode::Hitsounds => ClickMode::Both,
+            ClickMode::Both => ClickMode::Off,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClickMode::Off => "off",
+            ClickMode::Metronome => "metronome",
+            ClickMode::Hitsounds => "hitsounds",
+            ClickMode::Both => "both",
+        }
+    }
+}
+
+/// which of the loaded sounds is played for an event
+const DOWNBEAT: usize = 0;
+const BEAT: usize = 1;
+const HIT: usize = 2;
+
+/// SOURCE WHICH PLAYS SHORT SOUNDS AT FIXED POINTS IN THE SONG
+/// it is mixed in before the sink changes the speed so DT and NC stay in time
+pub struct ClickTrack {
+    sounds: Vec<Vec<f32>>,
+    events: Vec<(u64, usize)>,
+    next_event: usize,
+    playing: Vec<(usize, usize)>,
+    position: u64,
+    end: u64,
+}
+
+impl ClickTrack {
+    /// BUILDS THE CLICKS FOR A SONG, EMPTY IF THE MODE IS OFF
+    pub fn new(song: &Song, parent_path: &str, mode: ClickMode) -> Self {
+        let mut events = Vec::new();
+        if let ClickMode::Metronome | ClickMode::Both = mode {
+            events.append(&mut beat_events(song));
+        }
+        let mut hit_sound = click_sound(800.0);
+        if let ClickMode::Hitsounds | ClickMode::Both = mode {
+            if let Some(beatmap) = load_beatmap(parent_path, song) {
+                events.append(&mut beatmap.hit_objects.iter().map(|h| {
+                    (ms_to_sample(h.start_time.0 as f64), HIT)
+                }).collect());
+                if let Some(sound) = load_hitsound(parent_path, song, beatmap.sample_set) {
+                    hit_sound = sound;
+                }
+            }
+        }
+        events.sort_by_key(|(sample, _)| *sample);
+
+        Self {
+            sounds: vec![click_sound(1600.0), click_sound(1000.0), hit_sound],
+            events,
+            next_event: 0,
+            playing: Vec::new(),
+            position: 0,
+            end: ms_to_sample(song.length as f64 * 1000.0),
+        }
+    }
+}
+
+impl Iterator for ClickTrack {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.events.is_empty() || self.position >= self.end {
+            return None;
+        }
+
+        while self.next_event < self.events.len() && self.events[self.next_event].0 <= self.position {
+            self.playing.push((self.events[self.next_event].1, 0));
+            self.next_event += 1;
+        }
+
+        let mut value = 0.0;
+        for (sound, offset) in self.playing.iter_mut() {
+            value += self.sounds[*sound][*offset];
+            *offset += 1;
+        }
+        let sounds = &self.sounds;
+        self.playing.retain(|(sound, offset)| *offset < sounds[*sound].len());
+
+        self.position += 1;
+        Some(value * CLICK_GAIN)
+    }
+}
+
+impl Source for ClickTrack {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        CLICK_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f64(self.end as f64 / CLICK_RATE as f64))
+    }
+}
+
+#[inline]
+fn ms_to_sample(ms: f64) -> u64 {
+    (ms.max(0.0) / 1000.0 * CLICK_RATE as f64) as u64
+}
+
+/// A SHORT DECAYING SINE, USED WHEN THE MAP DOESNT HAVE ITS OWN SOUNDS
+fn click_sound(frequency: f32) -> Vec<f32> {
+    let length = CLICK_RATE as usize / 30;
+    (0..length).map(|i| {
+        let t = i as f32 / CLICK_RATE as f32;
+        let decay = 1.0 - i as f32 / length as f32;
+        (2.0 * PI * frequency * t).sin() * decay * decay
+    }).collect()
+}
+
+/// EVERY BEAT FROM THE UNINHERITED TIMING POINTS, THE FIRST OF EACH BAR IS A DOWNBEAT
+fn beat_events(song: &Song) -> Vec<(u64, usize)> {
+    let mut events = Vec::new();
+    let end = song.length as f64 * 1000.0;
+    let sections: Vec<_> = song.timing_points.iter().filter(|p| p.uninherited).collect();
+
+    for (i, point) in sections.iter().enumerate() {
+        let section_end = sections.get(i + 1).map_or(end, |p| p.time as f64);
+        let mut time = point.time as f64;
+        let mut beat = 0;
+        while time < section_end {
+            let sound = if beat % point.meter.max(1) == 0 { DOWNBEAT } else { BEAT };
+            events.push((ms_to_sample(time), sound));
+            time += point.beat_length;
+            beat += 1;
+        }
+    }
+    events
+}
+
+fn load_beatmap(parent_path: &str, song: &Song) -> Option<Beatmap> {
+    if song.beatmap_path.is_empty() {
+        return None;
+    }
+    let file = File::open(format!("{}{}", parent_path, song.beatmap_path)).ok()?;
+    Beatmap::parse(BufReader::new(file)).ok()
+}
+
+/// LOADS THE MAPS OWN HITNORMAL SAMPLE FROM ITS FOLDER IF IT HAS ONE
+fn load_hitsound(parent_path: &str, song: &Song, sample_set: SampleSet) -> Option<Vec<f32>> {
+    let set_name = match sample_set {
+        SampleSet::Soft => "soft",
+        SampleSet::Drum => "drum",
+        _ => "normal",
+    };
+    let beatmap_path = format!("{}{}", parent_path, song.beatmap_path);
+    let folder = Path::new(&beatmap_path).parent()?;
+
+    for extension in ["wav", "ogg"] {
+        let path = folder.join(format!("{}-hitnormal.{}", set_name, extension));
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(_) => continue,
+        };
+        let decoder = match Decoder::new(BufReader::new(file)) {
+            Ok(d) => d,
+            Err(_) => continue,
+        };
+        let uniform: UniformSourceIterator<_, f32> = UniformSourceIterator::new(decoder, 1, CLICK_RATE);
+        return Some(uniform.collect());
+    }
+    None
+}
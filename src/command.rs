@@ -0,0 +1,449 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::export::ExportFormat;
+use crate::osu::Mod;
+use crate::playlist_files::PlaylistFormat;
+use crate::query::{SortKey, split_words};
+use crate::theme::THEMES;
+use crate::layout::{Panel, Arrangement};
+
+/// how many typed commands are remembered between sessions
+const HISTORY_KEPT: usize = 100;
+
+/// every command with what it takes, for completing names and explaining mistakes
+const COMMANDS: [(&str, &str); 13] = [
+    ("playlist", "playlist new|delete <name> or playlist rename <name> <new name>"),
+    ("export", "export wav|flac [folder] for the hovered playbar song, or export m3u|xspf <playlist> [path]"),
+    ("import", "import <path>"),
+    ("rescan", "rescan"),
+    ("volume", "volume <0-100>"),
+    ("sort", "sort <key>"),
+    ("mod", "mod nm|dt|nc"),
+    ("theme", "theme <name>"),
+    ("hide", "hide <panel>"),
+    ("show", "show <panel>|all"),
+    ("layout", "layout auto|columns|stacked"),
+    ("save", "save"),
+    ("quit", "quit"),
+];
+
+/// something typed after a :, names with spaces in go in quotes
+#[derive(Clone, Debug)]
+pub enum Command {
+    NewPlaylist(String),
+    RenamePlaylist(String, String),
+    DeletePlaylist(String),
+    /// without a path it goes to the export folder
+    Export(PlaylistFormat, String, Option<String>),
+    /// the song hovered in the playbar, to the folder or the export folder
+    ExportSong(ExportFormat, Option<String>),
+    Import(String),
+    Rescan,
+    /// a percentage
+    Volume(u8),
+    Sort(SortKey),
+    /// the mod songs are added from the search with
+    Mod(Mod),
+    Theme(String),
+    Hide(Panel),
+    /// none shows every hidden panel
+    Show(Option<Panel>),
+    Arrange(Arrangement),
+    Save,
+    Quit,
+}
+
+impl Command {
+    /// READS A TYPED LINE, THE ERROR SAYS HOW THE COMMAND SHOULD HAVE BEEN WRITTEN
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let words = split_words(line.trim().trim_start_matches(':'));
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (name.to_lowercase(), args),
+            None => return Err(String::from("no command")),
+        };
+        let usage = || match COMMANDS.iter().find(|(n, _)| *n == name) {
+            Some((_, usage)) => format!("usage: :{}", usage),
+            None => format!("unknown command {}", name),
+        };
+
+        let command = match (name.as_str(), args) {
+            ("playlist", [sub, name]) if sub == "new" => Command::NewPlaylist(name.clone()),
+            ("playlist", [sub, name]) if sub == "delete" => Command::DeletePlaylist(name.clone()),
+            ("playlist", [sub, name, new_name]) if sub == "rename" => Command::RenamePlaylist(name.clone(), new_name.clone()),
+            ("export", [format, folder @ ..]) if folder.len() <= 1 && ExportFormat::from_name(format).is_some() => {
+                Command::ExportSong(ExportFormat::from_name(format).ok_or_else(usage)?, folder.first().cloned())
+            }
+            ("export", [format, playlist, path @ ..]) if path.len() <= 1 => {
+                let format = PlaylistFormat::from_name(format).ok_or_else(usage)?;
+                Command::Export(format, playlist.clone(), path.first().cloned())
+            }
+            ("import", [path]) => Command::Import(path.clone()),
+            ("rescan", []) => Command::Rescan,
+            ("volume", [volume]) => match volume.trim_end_matches('%').parse() {
+                Ok(v) if v <= 100 => Command::Volume(v),
+                _ => return Err(usage()),
+            },
+            ("sort", [key]) => match SortKey::from_name(&key.to_lowercase()) {
+                Some(key) => Command::Sort(key),
+                None => return Err(format!("can't sort by {}, try {}", key, SortKey::names().collect::<Vec<_>>().join(", "))),
+            },
+            ("mod", [modifier]) => Command::Mod(Mod::from_name(modifier).ok_or_else(usage)?),
+            ("theme", [theme]) => Command::Theme(theme.to_lowercase()),
+            ("hide", [panel]) => Command::Hide(Panel::from_name(&panel.to_lowercase()).ok_or_else(usage)?),
+            ("show", [panel]) if panel.eq_ignore_ascii_case("all") => Command::Show(None),
+            ("show", [panel]) => Command::Show(Some(Panel::from_name(&panel.to_lowercase()).ok_or_else(usage)?)),
+            ("layout", [arrangement]) => Command::Arrange(Arrangement::from_name(&arrangement.to_lowercase()).ok_or_else(usage)?),
+            ("save", []) => Command::Save,
+            ("quit" | "q", []) => Command::Quit,
+            _ => return Err(usage()),
+        };
+        Ok(command)
+    }
+}
+
+/// ~ AT THE START IS THE HOME FOLDER, LIKE IN A SHELL
+pub fn expand_home(path: &str) -> PathBuf {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"));
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// words with spaces in are quoted so they stay one word
+fn quote(word: &str) -> String {
+    match word.contains(' ') {
+        true => format!("\"{}\"", word),
+        false => word.to_string(),
+    }
+}
+
+/// SPLITS OFF THE WORD BEING TYPED, ITS QUOTES ARE DROPPED
+fn split_partial(line: &str) -> (&str, String) {
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ' ' if !quoted => start = i + 1,
+            _ => {}
+        }
+    }
+    let (base, partial) = line.split_at(start);
+    (base, partial.replace('"', ""))
+}
+
+/// FILES AND FOLDERS STARTING WITH WHAT HAS BEEN TYPED, FOLDERS END IN / SO THE NEXT TAB GOES INTO THEM
+fn complete_path(partial: &str) -> Vec<String> {
+    if partial == "~" {
+        return vec![String::from("~/")];
+    }
+    let (directory, prefix) = match partial.rfind('/') {
+        Some(i) => partial.split_at(i + 1),
+        None => ("", partial),
+    };
+    let entries = match fs::read_dir(if directory.is_empty() { PathBuf::from(".") } else { expand_home(directory) }) {
+        Ok(e) => e,
+        Err(_) => return Vec::new(),
+    };
+    let mut paths: Vec<String> = entries.filter_map(|e| e.ok()).filter_map(|e| {
+        let name = e.file_name().to_string_lossy().to_string();
+        // hidden files only come up once a . has been typed
+        if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+            return None;
+        }
+        let slash = if e.path().is_dir() { "/" } else { "" };
+        Some(format!("{}{}{}", directory, name, slash))
+    }).collect();
+    paths.sort();
+    paths
+}
+
+/// WHAT THE WORD BEING TYPED COULD BE, GOING BY THE WORDS BEFORE IT
+fn candidates(before: &[String], partial: &str, playlists: &[&str]) -> Vec<String> {
+    let before: Vec<String> = before.iter().map(|w| w.to_lowercase()).collect();
+    let before: Vec<&str> = before.iter().map(|w| w.as_str()).collect();
+    let options: Vec<String> = match before.as_slice() {
+        [] => COMMANDS.iter().map(|(name, _)| name.to_string()).collect(),
+        ["playlist"] => vec![String::from("new"), String::from("rename"), String::from("delete")],
+        ["export", "wav" | "flac"] => return complete_path(partial),
+        ["playlist", "rename" | "delete"] | ["export", _] => playlists.iter().map(|p| p.to_string()).collect(),
+        ["export"] => vec![String::from("m3u"), String::from("xspf"), String::from("wav"), String::from("flac")],
+        ["export", _, _] | ["import"] => return complete_path(partial),
+        ["sort"] => SortKey::names().map(String::from).collect(),
+        ["mod"] => vec![String::from("nm"), String::from("dt"), String::from("nc")],
+        ["theme"] => THEMES.iter().map(|(name, _)| name.to_string()).collect(),
+        ["hide"] => Panel::ALL.iter().map(|p| p.name().to_string()).collect(),
+        ["show"] => Panel::ALL.iter().map(|p| p.name().to_string()).chain([String::from("all")]).collect(),
+        ["layout"] => Arrangement::ALL.iter().map(|a| a.name().to_string()).collect(),
+        _ => Vec::new(),
+    };
+    let partial = partial.to_lowercase();
+    options.into_iter().filter(|o| o.to_lowercase().starts_with(&partial)).collect()
+}
+
+/// the words tab is going through
+#[derive(Debug)]
+struct Completion {
+    /// the line up to the word being completed
+    base: String,
+    options: Vec<String>,
+    i: usize,
+    /// what tab last put in the line, typing anything else starts again
+    line: String,
+}
+
+/// THE : LINE, WITH WHAT HAS BEEN TYPED BEFORE AND WHAT TAB IS GOING THROUGH
+#[derive(Debug, Default)]
+pub struct CommandLine {
+    /// whats been typed so far, the line is only open while this is set
+    pub text: Option<String>,
+    history: Vec<String>,
+    // how far back up has gone, none is the line being typed
+    history_i: Option<usize>,
+    // the half typed line to come back to at the bottom of the history
+    draft: String,
+    completion: Option<Completion>,
+}
+
+impl CommandLine {
+    pub fn new(mut history: Vec<String>) -> Self {
+        if history.len() > HISTORY_KEPT {
+            history.drain(..history.len() - HISTORY_KEPT);
+        }
+        Self { history, ..Self::default() }
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.text.is_some()
+    }
+
+    pub fn open(&mut self) {
+        self.text = Some(String::new());
+        self.history_i = None;
+        self.completion = None;
+    }
+
+    pub fn close(&mut self) {
+        self.text = None;
+    }
+
+    /// the other words tab would go through, for showing alongside the line
+    pub fn options(&self) -> &[String] {
+        match &self.completion {
+            Some(c) if c.options.len() > 1 && self.text.as_ref() == Some(&c.line) => &c.options,
+            _ => &[],
+        }
+    }
+
+    pub fn push(&mut self, c: char) {
+        if let Some(text) = &mut self.text {
+            text.push(c);
+        }
+    }
+
+    /// backspace on an empty line closes it, like in vim
+    pub fn pop(&mut self) {
+        match &mut self.text {
+            Some(text) if !text.is_empty() => {
+                text.pop();
+            }
+            _ => self.close(),
+        }
+    }
+
+    /// GOES BACK THROUGH THE HISTORY WITH UP AND FORWARD WITH DOWN, THE TYPED LINE IS AT THE BOTTOM
+    pub fn move_history(&mut self, up: bool) {
+        let text = match &mut self.text {
+            Some(t) => t,
+            None => return,
+        };
+        let last = match self.history.len() {
+            0 => return,
+            n => n - 1,
+        };
+        self.history_i = match (up, self.history_i) {
+            (true, None) => {
+                self.draft = text.clone();
+                Some(last)
+            }
+            (true, Some(i)) => Some(i.saturating_sub(1)),
+            (false, Some(i)) if i < last => Some(i + 1),
+            (false, _) => None,
+        };
+        *text = match self.history_i {
+            Some(i) => self.history[i].clone(),
+            None => self.draft.clone(),
+        };
+    }
+
+    /// FILLS IN THE WORD BEING TYPED, PRESSING AGAIN GOES TO THE NEXT MATCH
+    /// gives back how many matches there were
+    pub fn complete(&mut self, playlists: &[&str]) -> usize {
+        let text = match &mut self.text {
+            Some(t) => t,
+            None => return 0,
+        };
+        let completion = match self.completion.take() {
+            // one match has nothing to go on to, a folder gets looked inside instead
+            Some(mut c) if c.line == *text && c.options.len() > 1 => {
+                c.i = (c.i + 1) % c.options.len();
+                c
+            }
+            _ => {
+                let (base, partial) = split_partial(text);
+                let options = candidates(&split_words(base), &partial, playlists);
+                if options.is_empty() {
+                    return 0;
+                }
+                Completion { base: base.to_string(), options, i: 0, line: String::new() }
+            }
+        };
+
+        let option = &completion.options[completion.i];
+        // a single match is finished off so the next word can be typed straight away
+        let space = if completion.options.len() == 1 && !option.ends_with('/') { " " } else { "" };
+        *text = format!("{}{}{}", completion.base, quote(option), space);
+        let count = completion.options.len();
+        self.completion = Some(Completion { line: text.clone(), ..completion });
+        count
+    }
+
+    /// CLOSES THE LINE AND GIVES BACK WHAT WAS TYPED, REMEMBERING IT FOR NEXT TIME
+    pub fn submit(&mut self) -> Option<String> {
+        let line = self.text.take()?.trim().to_string();
+        if line.is_empty() {
+            return None;
+        }
+        if self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+            if self.history.len() > HISTORY_KEPT {
+                self.history.remove(0);
+            }
+        }
+        Some(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_parse() {
+        assert!(matches!(
+            Command::parse(":export m3u gym ~/gym.m3u"),
+            Ok(Command::Export(PlaylistFormat::M3u, name, Some(path))) if name == "gym" && path == "~/gym.m3u"
+        ));
+        assert!(matches!(Command::parse("export xspf gym"), Ok(Command::Export(PlaylistFormat::Xspf, _, None))));
+        assert!(matches!(Command::parse("export FLAC"), Ok(Command::ExportSong(ExportFormat::Flac, None))));
+        assert!(matches!(
+            Command::parse(r#"export wav "~/my exports""#),
+            Ok(Command::ExportSong(ExportFormat::Wav, Some(folder))) if folder == "~/my exports"
+        ));
+        assert!(matches!(Command::parse("volume 40%"), Ok(Command::Volume(40))));
+        assert!(matches!(Command::parse("sort BPM"), Ok(Command::Sort(SortKey::Bpm))));
+        assert!(matches!(Command::parse("show all"), Ok(Command::Show(None))));
+        assert!(matches!(Command::parse("show Songs"), Ok(Command::Show(Some(Panel::Songs)))));
+        assert!(matches!(Command::parse("layout stacked"), Ok(Command::Arrange(Arrangement::Stacked))));
+        assert!(matches!(Command::parse(" :q "), Ok(Command::Quit)));
+    }
+
+    #[test]
+    fn quoted_names_keep_their_spaces() {
+        assert!(matches!(
+            Command::parse(r#"playlist rename "gym mix" "old gym""#),
+            Ok(Command::RenamePlaylist(name, new_name)) if name == "gym mix" && new_name == "old gym"
+        ));
+        assert!(matches!(Command::parse(r#"playlist new "late night""#), Ok(Command::NewPlaylist(n)) if n == "late night"));
+        assert!(matches!(Command::parse("import \"my music/a b.m3u\""), Ok(Command::Import(p)) if p == "my music/a b.m3u"));
+    }
+
+    #[test]
+    fn mistakes_say_how_to_write_it() {
+        assert_eq!(Command::parse("").unwrap_err(), "no command");
+        assert_eq!(Command::parse("dance").unwrap_err(), "unknown command dance");
+        assert_eq!(Command::parse("volume 101").unwrap_err(), "usage: :volume <0-100>");
+        assert_eq!(Command::parse("playlist new gym mix").unwrap_err(), format!("usage: :{}", COMMANDS[0].1));
+        assert_eq!(Command::parse("export ogg gym").unwrap_err(), format!("usage: :{}", COMMANDS[1].1));
+        assert!(Command::parse("export flac gym extra").is_err());
+        assert!(Command::parse("sort colour").unwrap_err().starts_with("can't sort by colour"));
+        assert!(Command::parse("hide everything").is_err());
+    }
+
+    #[test]
+    fn the_last_word_is_split_off() {
+        assert_eq!(split_partial("export m3u gy"), ("export m3u ", String::from("gy")));
+        assert_eq!(split_partial("export m3u "), ("export m3u ", String::new()));
+        assert_eq!(split_partial(r#"playlist delete "gym m"#), ("playlist delete ", String::from("gym m")));
+        assert_eq!(split_partial("vol"), ("", String::from("vol")));
+    }
+
+    #[test]
+    fn candidates_follow_the_words_before() {
+        let words = |line: &str| split_words(line);
+        let playlists = ["gym", "Gym Mix", "chill"];
+        assert_eq!(candidates(&[], "s", &playlists), vec!["sort", "show", "save"]);
+        assert_eq!(candidates(&words("export"), "", &playlists), vec!["m3u", "xspf", "wav", "flac"]);
+        assert_eq!(candidates(&words("export m3u"), "gy", &playlists), vec!["gym", "Gym Mix"]);
+        assert_eq!(candidates(&words("PLAYLIST"), "re", &playlists), vec!["rename"]);
+        assert_eq!(candidates(&words("show"), "a", &playlists), vec!["all"]);
+        assert!(candidates(&words("playlist new"), "", &playlists).is_empty());
+        assert!(candidates(&words("rescan"), "", &playlists).is_empty());
+    }
+
+    #[test]
+    fn tab_quotes_names_and_goes_round() {
+        let mut line = CommandLine::new(Vec::new());
+        line.open();
+        "playlist delete g".chars().for_each(|c| line.push(c));
+        assert_eq!(line.complete(&["gym", "gym mix"]), 2);
+        assert_eq!(line.text.as_deref(), Some("playlist delete gym"));
+        line.complete(&["gym", "gym mix"]);
+        assert_eq!(line.text.as_deref(), Some(r#"playlist delete "gym mix""#));
+        line.complete(&["gym", "gym mix"]);
+        assert_eq!(line.text.as_deref(), Some("playlist delete gym"));
+
+        line.text = Some(String::from("la"));
+        assert_eq!(line.complete(&[]), 1);
+        assert_eq!(line.text.as_deref(), Some("layout "));
+    }
+
+    #[test]
+    fn home_is_expanded_at_the_start_only() {
+        let home = match env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) {
+            Some(h) => PathBuf::from(h),
+            None => return,
+        };
+        assert_eq!(expand_home("~"), home);
+        assert_eq!(expand_home("~/gym.m3u"), home.join("gym.m3u"));
+        assert_eq!(expand_home("~bob/gym.m3u"), PathBuf::from("~bob/gym.m3u"));
+        assert_eq!(expand_home("a/~/b"), PathBuf::from("a/~/b"));
+    }
+
+    #[test]
+    fn history_goes_back_to_the_draft() {
+        let mut line = CommandLine::new(vec![String::from("save"), String::from("rescan")]);
+        line.open();
+        line.push('v');
+        line.move_history(true);
+        assert_eq!(line.text.as_deref(), Some("rescan"));
+        line.move_history(true);
+        line.move_history(true);
+        assert_eq!(line.text.as_deref(), Some("save"));
+        line.move_history(false);
+        line.move_history(false);
+        assert_eq!(line.text.as_deref(), Some("v"));
+        assert_eq!(line.submit(), Some(String::from("v")));
+        assert_eq!(line.history(), ["save", "rescan", "v"]);
+        assert!(!line.is_open());
+    }
+}
@@ -0,0 +1,336 @@
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use rodio::Source;
+use rodio::source::UniformSourceIterator;
+
+use crate::click::ClickMode;
+use crate::osu::{Song, Entry, Mod};
+use crate::player::build_source;
+
+/// samples per flac frame
+const FLAC_BLOCK_SIZE: usize = 4096;
+
+#[derive(Copy, Clone, Debug)]
+pub enum ExportFormat {
+    Wav,
+    Flac,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Wav => "wav",
+            ExportFormat::Flac => "flac",
+        }
+    }
+
+    /// the format as its written on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "wav" => Some(ExportFormat::Wav),
+            "flac" => Some(ExportFormat::Flac),
+            _ => None,
+        }
+    }
+}
+
+/// THE NAME THE EXPORTED FILE GETS, LIKE "Artist - Title (NC).wav"
+pub fn export_name(song: &Song, entry: &Entry, format: ExportFormat) -> String {
+    let tag = match (entry.modifier, entry.rate) {
+        (Mod::NoMod, None) => String::new(),
+        _ => format!(" ({})", entry.tag()),
+    };
+    let name = format!("{} - {}{}", song.artist, song.song_name, tag);
+    format!("{}.{}", sanitize_file_name(&name), format.extension())
+}
+
+/// WINDOWS DOESNT LIKE THESE IN FILE NAMES
+pub fn sanitize_file_name(name: &str) -> String {
+    name.chars().map(|c| match c {
+        '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+        c => c,
+    }).collect()
+}
+
+/// RENDERS THE SONG WITH THE MOD OR CUSTOM RATE OF THE ENTRY AND WRITES IT TO THE DIRECTORY
+pub fn export_song(song: &Song, entry: &Entry, parent_path: &str, format: ExportFormat, directory: &str) -> Result<PathBuf, String> {
+    fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+    let path = Path::new(directory).join(export_name(song, entry, format));
+
+    // same chain as playback, then sped up the same way the sink does it
+    let source = build_source(song, parent_path, ClickMode::Off)?;
+    let channels = source.channels();
+    let sample_rate = source.sample_rate();
+    let speed = entry.speed();
+    let samples = UniformSourceIterator::<_, i16>::new(source.speed(speed), channels, sample_rate);
+
+    match format {
+        ExportFormat::Wav => write_wav(&path, samples, channels, sample_rate)?,
+        // the frame sizes go in the header, so flac needs the whole song first
+        ExportFormat::Flac => {
+            let samples: Vec<i16> = samples.collect();
+            let data = encode_flac(&samples, channels, sample_rate);
+            fs::write(&path, data).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(path)
+}
+
+/// WRITES THE SAMPLES AS THEYRE RENDERED SO THE SONG IS NEVER ALL IN MEMORY
+fn write_wav(path: &Path, samples: impl Iterator<Item = i16>, channels: u16, sample_rate: u32) -> Result<(), String> {
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let file = BufWriter::new(File::create(path).map_err(|e| e.to_string())?);
+    let mut writer = hound::WavWriter::new(file, spec).map_err(|e| e.to_string())?;
+    for sample in samples {
+        writer.write_sample(sample).map_err(|e| e.to_string())?;
+    }
+    writer.finalize().map_err(|e| e.to_string())
+}
+
+/// WRITES BITS MOST SIGNIFICANT FIRST, WHICH IS WHAT FLAC WANTS
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u64,
+    filled: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self { bytes: Vec::new(), current: 0, filled: 0 }
+    }
+
+    fn write(&mut self, value: u64, bits: u32) {
+        for i in (0..bits).rev() {
+            self.current = (self.current << 1) | ((value >> i) & 1);
+            self.filled += 1;
+            if self.filled == 8 {
+                self.bytes.push(self.current as u8);
+                self.current = 0;
+                self.filled = 0;
+            }
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64 & ((1 << bits) - 1), bits);
+    }
+
+    fn write_unary(&mut self, zeros: u64) {
+        for _ in 0..zeros {
+            self.write(0, 1);
+        }
+        self.write(1, 1);
+    }
+
+    fn align(&mut self) {
+        if self.filled != 0 {
+            self.write(0, 8 - self.filled);
+        }
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// FRAME NUMBERS ARE STORED THE SAME WAY UTF-8 STORES CHARACTERS
+fn write_utf8_number(writer: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        writer.write(value, 8);
+        return;
+    }
+    let mut extra = 1;
+    while value >= 1 << (5 * extra + 6) {
+        extra += 1;
+    }
+    let lead_marker = (0xFF00u64 >> (extra + 1)) & 0xFF;
+    writer.write(lead_marker | (value >> (6 * extra)), 8);
+    for i in (0..extra).rev() {
+        writer.write(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+    }
+}
+
+/// ENCODES ONE CHANNEL OF A BLOCK AS A FIXED PREDICTOR SUBFRAME WITH RICE CODED RESIDUALS
+fn write_subframe(writer: &mut BitWriter, samples: &[i64]) {
+    let order = samples.len().min(2);
+    writer.write(0, 1);
+    writer.write(0b001000 | order as u64, 6);
+    writer.write(0, 1);
+
+    for sample in &samples[..order] {
+        writer.write_signed(*sample, 16);
+    }
+
+    let residuals: Vec<u64> = (order..samples.len()).map(|i| {
+        let residual = match order {
+            2 => samples[i] - 2 * samples[i - 1] + samples[i - 2],
+            _ => samples[i] - samples[i - 1],
+        };
+        // folds negatives in between the positives
+        ((residual << 1) ^ (residual >> 63)) as u64
+    }).collect();
+
+    // picks the rice parameter which takes the least space
+    let parameter = (0..15u32).min_by_key(|k| {
+        residuals.iter().map(|r| (r >> k) + 1 + *k as u64).sum::<u64>()
+    }).unwrap_or(0);
+
+    writer.write(0, 2);
+    writer.write(0, 4);
+    writer.write(parameter as u64, 4);
+    for residual in residuals {
+        writer.write_unary(residual >> parameter);
+        writer.write(residual & ((1 << parameter) - 1), parameter);
+    }
+}
+
+/// ENCODES INTERLEAVED 16 BIT SAMPLES AS A FLAC FILE
+pub fn encode_flac(samples: &[i16], channels: u16, sample_rate: u32) -> Vec<u8> {
+    let channels = channels.max(1) as usize;
+    let total_frames = samples.len() / channels;
+
+    let mut writer = BitWriter::new();
+    writer.write(u32::from_be_bytes(*b"fLaC") as u64, 32);
+
+    // streaminfo is the only metadata block
+    writer.write(1, 1);
+    writer.write(0, 7);
+    writer.write(34, 24);
+    writer.write(FLAC_BLOCK_SIZE as u64, 16);
+    writer.write(FLAC_BLOCK_SIZE as u64, 16);
+    writer.write(0, 24);
+    writer.write(0, 24);
+    writer.write(sample_rate as u64, 20);
+    writer.write(channels as u64 - 1, 3);
+    writer.write(15, 5);
+    writer.write(total_frames as u64, 36);
+    writer.write(0, 64);
+    writer.write(0, 64);
+
+    let mut output = writer.bytes;
+    for (number, start) in (0..total_frames).step_by(FLAC_BLOCK_SIZE).enumerate() {
+        let block_size = FLAC_BLOCK_SIZE.min(total_frames - start);
+        let mut frame = BitWriter::new();
+
+        frame.write(0b11111111111110, 14);
+        frame.write(0, 1);
+        frame.write(0, 1);
+        // full blocks have their own code, the last one stores its size after the number
+        let size_code = if block_size == FLAC_BLOCK_SIZE { 0b1100 } else { 0b0111 };
+        frame.write(size_code, 4);
+        frame.write(0, 4);
+        frame.write(channels as u64 - 1, 4);
+        frame.write(0b100, 3);
+        frame.write(0, 1);
+        write_utf8_number(&mut frame, number as u64);
+        if size_code == 0b0111 {
+            frame.write(block_size as u64 - 1, 16);
+        }
+        let crc = crc8(&frame.bytes);
+        frame.write(crc as u64, 8);
+
+        for channel in 0..channels {
+            let channel_samples: Vec<i64> = (start..start + block_size)
+                .map(|i| samples[i * channels + channel] as i64)
+                .collect();
+            write_subframe(&mut frame, &channel_samples);
+        }
+        frame.align();
+        let crc = crc16(&frame.bytes);
+        frame.write(crc as u64, 16);
+        output.append(&mut frame.bytes);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a sine with some noise and both extremes thrown in, so residuals of every size come up
+    fn test_samples(len: usize) -> Vec<i16> {
+        let mut seed = 12345u32;
+        (0..len).map(|i| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            match i % 997 {
+                0 => i16::MIN,
+                1 => i16::MAX,
+                _ => ((i as f32 * 0.05).sin() * 20000.0) as i16 + (seed >> 16) as i16 % 500,
+            }
+        }).collect()
+    }
+
+    fn decode(data: &[u8]) -> (claxon::metadata::StreamInfo, Vec<i16>) {
+        let mut reader = claxon::FlacReader::new(data).unwrap();
+        let info = reader.streaminfo();
+        let samples = reader.samples().map(|s| s.unwrap() as i16).collect();
+        (info, samples)
+    }
+
+    #[test]
+    fn flac_round_trips_mono() {
+        let samples = test_samples(FLAC_BLOCK_SIZE * 2 + 123);
+        let (info, decoded) = decode(&encode_flac(&samples, 1, 44100));
+        assert_eq!(info.channels, 1);
+        assert_eq!(info.sample_rate, 44100);
+        assert_eq!(info.samples, Some(samples.len() as u64));
+        assert_eq!(decoded, samples);
+    }
+
+    #[test]
+    fn flac_round_trips_stereo() {
+        let samples = test_samples((FLAC_BLOCK_SIZE * 3 + 1) * 2);
+        let (info, decoded) = decode(&encode_flac(&samples, 2, 48000));
+        assert_eq!(info.channels, 2);
+        assert_eq!(info.sample_rate, 48000);
+        assert_eq!(info.samples, Some(samples.len() as u64 / 2));
+        assert_eq!(decoded, samples);
+    }
+
+    #[test]
+    fn flac_round_trips_short_and_empty() {
+        for len in [0, 1, 2, 3] {
+            let samples = test_samples(len);
+            assert_eq!(decode(&encode_flac(&samples, 1, 44100)).1, samples);
+        }
+    }
+
+    #[test]
+    fn frame_numbers_use_utf8_lengths() {
+        let encode = |value| {
+            let mut writer = BitWriter::new();
+            write_utf8_number(&mut writer, value);
+            writer.bytes
+        };
+        assert_eq!(encode(0x7F), vec![0x7F]);
+        assert_eq!(encode(0x80), vec![0xC2, 0x80]);
+        assert_eq!(encode(0x7FF), vec![0xDF, 0xBF]);
+        assert_eq!(encode(0x800), vec![0xE0, 0xA0, 0x80]);
+        assert_eq!(encode(0x10000), "\u{10000}".as_bytes().to_vec());
+    }
+}
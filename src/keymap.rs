@@ -0,0 +1,689 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+use crate::serialize;
+
+/// which set of keys is in use, the playlist panel has one for the list and one for inside a playlist
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Section {
+    /// works from every panel, so the panels cant bind the same keys
+    Global,
+    Search,
+    PlayBar,
+    Playlists,
+    PlaylistSongs,
+}
+
+impl Section {
+    pub const ALL: [Section; 5] = [
+        Section::Global,
+        Section::Search,
+        Section::PlayBar,
+        Section::Playlists,
+        Section::PlaylistSongs,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Section::Global => "global",
+            Section::Search => "search",
+            Section::PlayBar => "playbar",
+            Section::Playlists => "playlists",
+            Section::PlaylistSongs => "playlist_songs",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.name() == name)
+    }
+}
+
+/// everything a key can be bound to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    NextPanel,
+    Rescan,
+    Stats,
+    Undo,
+    Redo,
+    Help,
+    CommandLine,
+    Maximize,
+    HidePanel,
+    ShowPanels,
+    /// esc, what it backs out of depends on the panel
+    Back,
+    Up,
+    Down,
+    AddToPlaylist,
+    CycleSearchMod,
+    Enqueue,
+    PlayNext,
+    CycleSort,
+    Favourite,
+    /// 0 clears the rating
+    Rate(u8),
+    EditTags,
+    EditNote,
+    Play,
+    Previous,
+    Skip,
+    RepeatOne,
+    ToggleHistory,
+    ClearQueue,
+    Pause,
+    ClickTrack,
+    ExportWav,
+    ExportFlac,
+    Visualizer,
+    CycleMod,
+    Faster,
+    Slower,
+    Remove,
+    Open,
+    NewPlaylist,
+    Save,
+    Rename,
+    Duplicate,
+    QueuePlaylist,
+    Import,
+    NewSmartPlaylist,
+    EditQuery,
+    ExportM3u,
+    ExportXspf,
+    Close,
+    PlayPlaylist,
+    ToggleRepeat,
+    ToggleShuffle,
+    Grab,
+    DragUp,
+    DragDown,
+}
+
+impl Action {
+    const ALL: [Action; 60] = [
+        Action::NextPanel, Action::Rescan, Action::Stats, Action::Undo, Action::Redo, Action::Help, Action::CommandLine,
+        Action::Maximize, Action::HidePanel, Action::ShowPanels,
+        Action::Back, Action::Up, Action::Down,
+        Action::AddToPlaylist, Action::CycleSearchMod, Action::Enqueue, Action::PlayNext, Action::CycleSort,
+        Action::Favourite, Action::Rate(0), Action::Rate(1), Action::Rate(2), Action::Rate(3), Action::Rate(4), Action::Rate(5),
+        Action::EditTags, Action::EditNote,
+        Action::Play, Action::Previous, Action::Skip, Action::RepeatOne, Action::ToggleHistory, Action::ClearQueue,
+        Action::Pause, Action::ClickTrack, Action::ExportWav, Action::ExportFlac, Action::Visualizer,
+        Action::CycleMod, Action::Faster, Action::Slower, Action::Remove,
+        Action::Open, Action::NewPlaylist, Action::Save, Action::Rename, Action::Duplicate, Action::QueuePlaylist,
+        Action::Import, Action::NewSmartPlaylist, Action::EditQuery, Action::ExportM3u, Action::ExportXspf,
+        Action::Close, Action::PlayPlaylist, Action::ToggleRepeat, Action::ToggleShuffle, Action::Grab,
+        Action::DragUp, Action::DragDown,
+    ];
+
+    /// the name used for it in the keys file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::NextPanel => "next_panel",
+            Action::Rescan => "rescan",
+            Action::Stats => "stats",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Help => "help",
+            Action::CommandLine => "command_line",
+            Action::Maximize => "maximize",
+            Action::HidePanel => "hide_panel",
+            Action::ShowPanels => "show_panels",
+            Action::Back => "back",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::AddToPlaylist => "add_to_playlist",
+            Action::CycleSearchMod => "cycle_search_mod",
+            Action::Enqueue => "queue",
+            Action::PlayNext => "play_next",
+            Action::CycleSort => "cycle_sort",
+            Action::Favourite => "favourite",
+            Action::Rate(0) => "clear_rating",
+            Action::Rate(1) => "rate_1",
+            Action::Rate(2) => "rate_2",
+            Action::Rate(3) => "rate_3",
+            Action::Rate(4) => "rate_4",
+            Action::Rate(_) => "rate_5",
+            Action::EditTags => "edit_tags",
+            Action::EditNote => "edit_note",
+            Action::Play => "play",
+            Action::Previous => "previous",
+            Action::Skip => "skip",
+            Action::RepeatOne => "repeat_one",
+            Action::ToggleHistory => "toggle_history",
+            Action::ClearQueue => "clear_queue",
+            Action::Pause => "pause",
+            Action::ClickTrack => "click_track",
+            Action::ExportWav => "export_wav",
+            Action::ExportFlac => "export_flac",
+            Action::Visualizer => "visualizer",
+            Action::CycleMod => "cycle_mod",
+            Action::Faster => "faster",
+            Action::Slower => "slower",
+            Action::Remove => "remove",
+            Action::Open => "open",
+            Action::NewPlaylist => "new_playlist",
+            Action::Save => "save",
+            Action::Rename => "rename",
+            Action::Duplicate => "duplicate",
+            Action::QueuePlaylist => "queue_playlist",
+            Action::Import => "import",
+            Action::NewSmartPlaylist => "new_smart_playlist",
+            Action::EditQuery => "edit_query",
+            Action::ExportM3u => "export_m3u",
+            Action::ExportXspf => "export_xspf",
+            Action::Close => "close",
+            Action::PlayPlaylist => "play_playlist",
+            Action::ToggleRepeat => "toggle_repeat",
+            Action::ToggleShuffle => "toggle_shuffle",
+            Action::Grab => "grab",
+            Action::DragUp => "drag_up",
+            Action::DragDown => "drag_down",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.name() == name)
+    }
+
+    /// what it does, for the help popup
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::NextPanel => "move to the next panel",
+            Action::Rescan => "rescan the song folder",
+            Action::Stats => "listening stats",
+            Action::Undo => "undo the last edit",
+            Action::Redo => "redo what was undone",
+            Action::Help => "show these keys",
+            Action::CommandLine => "type a command like :volume 40",
+            Action::Maximize => "fill the screen with the focused panel, again to go back",
+            Action::HidePanel => "hide the focused panel",
+            Action::ShowPanels => "show the hidden panels again",
+            Action::Back => "back out",
+            Action::Up => "move up",
+            Action::Down => "move down",
+            Action::AddToPlaylist => "add the song to the selected playlist",
+            Action::CycleSearchMod => "change the mod songs are added with",
+            Action::Enqueue => "queue the song",
+            Action::PlayNext => "play the song next",
+            Action::CycleSort => "change what the songs are sorted by",
+            Action::Favourite => "favourite the song",
+            Action::Rate(0) => "clear the rating",
+            Action::Rate(1) => "rate the song 1 star",
+            Action::Rate(2) => "rate the song 2 stars",
+            Action::Rate(3) => "rate the song 3 stars",
+            Action::Rate(4) => "rate the song 4 stars",
+            Action::Rate(_) => "rate the song 5 stars",
+            Action::EditTags => "edit the song's tags",
+            Action::EditNote => "edit the song's note",
+            Action::Play => "play the hovered song",
+            Action::Previous => "restart or go back a song",
+            Action::Skip => "skip the song",
+            Action::RepeatOne => "repeat the song",
+            Action::ToggleHistory => "switch between up next and history",
+            Action::ClearQueue => "clear the queue",
+            Action::Pause => "pause or play",
+            Action::ClickTrack => "change the click track",
+            Action::ExportWav => "export the song as wav",
+            Action::ExportFlac => "export the song as flac",
+            Action::Visualizer => "change the visualizer",
+            Action::CycleMod => "change the song's mod",
+            Action::Faster => "speed the song up",
+            Action::Slower => "slow the song down",
+            Action::Remove => "remove it",
+            Action::Open => "open the playlist",
+            Action::NewPlaylist => "new playlist",
+            Action::Save => "save the playlists",
+            Action::Rename => "rename the playlist",
+            Action::Duplicate => "copy the playlist",
+            Action::QueuePlaylist => "queue the whole playlist",
+            Action::Import => "import a playlist file",
+            Action::NewSmartPlaylist => "new smart playlist from a query",
+            Action::EditQuery => "edit the smart playlist's query",
+            Action::ExportM3u => "export the playlist as m3u",
+            Action::ExportXspf => "export the playlist as xspf",
+            Action::Close => "close the playlist",
+            Action::PlayPlaylist => "play the playlist",
+            Action::ToggleRepeat => "repeat the playlist",
+            Action::ToggleShuffle => "shuffle the playlist",
+            Action::Grab => "pick the song up to move it",
+            Action::DragUp => "move the song up",
+            Action::DragDown => "move the song down",
+        }
+    }
+}
+
+/// A KEY WITH ITS MODIFIERS, SHIFT IS FOLDED INTO LETTERS SO `Q` AND `shift+q` ARE THE SAME KEY
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    fn new(mut code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        if let KeyCode::Char(c) = code {
+            if modifiers.contains(KeyModifiers::SHIFT) {
+                code = KeyCode::Char(c.to_ascii_uppercase());
+            }
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self { code, modifiers }
+    }
+
+    pub fn from_event(key: &KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+
+    /// PARSES THINGS LIKE `q`, `shift+up`, `ctrl+s` AND `ctrl++`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (modifier_text, key) = match text {
+            "+" => ("", "+"),
+            t if t.ends_with("++") => (&t[..t.len() - 2], "+"),
+            t => t.rsplit_once('+').unwrap_or(("", t)),
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in modifier_text.split('+').filter(|m| !m.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier '{}' in '{}'", modifier, text)),
+            };
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "enter" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                k => match k.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n) if (1..=12).contains(&n) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key '{}'", key)),
+                },
+            },
+        };
+        Ok(Self::new(code, modifiers))
+    }
+
+    /// plain characters are what gets typed into the search box
+    fn is_typed(&self) -> bool {
+        matches!(self.code, KeyCode::Char(_)) && self.modifiers.is_empty()
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            KeyCode::Esc => write!(f, "esc"),
+            KeyCode::PageUp => write!(f, "pageup"),
+            KeyCode::PageDown => write!(f, "pagedown"),
+            code => write!(f, "{}", format!("{:?}", code).to_lowercase()),
+        }
+    }
+}
+
+/// the keys for one action in the keys file, either one or a list of them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// THE KEYS FILE, A PRESET TO START FROM AND THEN ANY ACTIONS BOUND DIFFERENTLY IN EACH SECTION
+#[derive(Deserialize)]
+struct KeyConfig {
+    #[serde(default)]
+    preset: Option<String>,
+    #[serde(flatten)]
+    sections: HashMap<String, HashMap<String, Keys>>,
+}
+
+/// the keys every preset starts from, by section then action
+const DEFAULT_KEYS: &[(Section, Action, &[&str])] = &[
+    (Section::Global, Action::NextPanel, &["tab"]),
+    (Section::Global, Action::Rescan, &["f5"]),
+    (Section::Global, Action::Stats, &["f2"]),
+    (Section::Global, Action::Undo, &["ctrl+z"]),
+    (Section::Global, Action::Redo, &["ctrl+y"]),
+    // ? has to be typed in search so it only opens the help from the other panels
+    (Section::Global, Action::Help, &["f1"]),
+    (Section::Global, Action::Maximize, &["f3"]),
+    (Section::Global, Action::HidePanel, &["f4"]),
+    (Section::Global, Action::ShowPanels, &["f6"]),
+    (Section::PlayBar, Action::Help, &["?"]),
+    (Section::Playlists, Action::Help, &["?"]),
+    (Section::PlaylistSongs, Action::Help, &["?"]),
+    // : isnt global since queries use it too, f7 works from search as well
+    (Section::Global, Action::CommandLine, &["f7"]),
+    (Section::PlayBar, Action::CommandLine, &[":"]),
+    (Section::Playlists, Action::CommandLine, &[":"]),
+    (Section::PlaylistSongs, Action::CommandLine, &[":"]),
+
+    (Section::Search, Action::Back, &["esc"]),
+    (Section::Search, Action::Up, &["up"]),
+    (Section::Search, Action::Down, &["down"]),
+    (Section::Search, Action::AddToPlaylist, &["right"]),
+    (Section::Search, Action::CycleSearchMod, &["left"]),
+    (Section::Search, Action::Enqueue, &["enter"]),
+    (Section::Search, Action::PlayNext, &["ctrl+p"]),
+    (Section::Search, Action::CycleSort, &["ctrl+s"]),
+    (Section::Search, Action::Favourite, &["ctrl+f"]),
+    (Section::Search, Action::Rate(0), &["alt+0"]),
+    (Section::Search, Action::Rate(1), &["alt+1"]),
+    (Section::Search, Action::Rate(2), &["alt+2"]),
+    (Section::Search, Action::Rate(3), &["alt+3"]),
+    (Section::Search, Action::Rate(4), &["alt+4"]),
+    (Section::Search, Action::Rate(5), &["alt+5"]),
+    (Section::Search, Action::EditTags, &["ctrl+t"]),
+    (Section::Search, Action::EditNote, &["ctrl+n"]),
+
+    (Section::PlayBar, Action::Back, &["esc"]),
+    (Section::PlayBar, Action::Up, &["up"]),
+    (Section::PlayBar, Action::Down, &["down"]),
+    (Section::PlayBar, Action::Play, &["enter"]),
+    (Section::PlayBar, Action::Previous, &["p"]),
+    (Section::PlayBar, Action::Skip, &["n"]),
+    (Section::PlayBar, Action::RepeatOne, &["r"]),
+    (Section::PlayBar, Action::ToggleHistory, &["h"]),
+    (Section::PlayBar, Action::ClearQueue, &["C"]),
+    (Section::PlayBar, Action::Pause, &["space"]),
+    (Section::PlayBar, Action::ClickTrack, &["c"]),
+    (Section::PlayBar, Action::ExportWav, &["x"]),
+    (Section::PlayBar, Action::ExportFlac, &["X"]),
+    (Section::PlayBar, Action::Visualizer, &["v"]),
+    (Section::PlayBar, Action::Favourite, &["f"]),
+    (Section::PlayBar, Action::Rate(0), &["0"]),
+    (Section::PlayBar, Action::Rate(1), &["1"]),
+    (Section::PlayBar, Action::Rate(2), &["2"]),
+    (Section::PlayBar, Action::Rate(3), &["3"]),
+    (Section::PlayBar, Action::Rate(4), &["4"]),
+    (Section::PlayBar, Action::Rate(5), &["5"]),
+    (Section::PlayBar, Action::EditTags, &["t"]),
+    (Section::PlayBar, Action::EditNote, &["N"]),
+    (Section::PlayBar, Action::CycleMod, &["right"]),
+    (Section::PlayBar, Action::Faster, &["+"]),
+    (Section::PlayBar, Action::Slower, &["-"]),
+    (Section::PlayBar, Action::Remove, &["delete"]),
+
+    (Section::Playlists, Action::Back, &["esc"]),
+    (Section::Playlists, Action::Up, &["up"]),
+    (Section::Playlists, Action::Down, &["down"]),
+    (Section::Playlists, Action::Open, &["enter"]),
+    (Section::Playlists, Action::NewPlaylist, &["n"]),
+    (Section::Playlists, Action::Save, &["q"]),
+    (Section::Playlists, Action::Rename, &["r"]),
+    (Section::Playlists, Action::Duplicate, &["c"]),
+    (Section::Playlists, Action::QueuePlaylist, &["a"]),
+    (Section::Playlists, Action::Import, &["i"]),
+    (Section::Playlists, Action::NewSmartPlaylist, &["s"]),
+    (Section::Playlists, Action::EditQuery, &["S"]),
+    (Section::Playlists, Action::ExportM3u, &["x"]),
+    (Section::Playlists, Action::ExportXspf, &["X"]),
+    (Section::Playlists, Action::Remove, &["d"]),
+
+    (Section::PlaylistSongs, Action::Back, &["esc"]),
+    (Section::PlaylistSongs, Action::Up, &["up"]),
+    (Section::PlaylistSongs, Action::Down, &["down"]),
+    (Section::PlaylistSongs, Action::Close, &["left"]),
+    (Section::PlaylistSongs, Action::PlayPlaylist, &["enter"]),
+    (Section::PlaylistSongs, Action::Save, &["q"]),
+    (Section::PlaylistSongs, Action::QueuePlaylist, &["a"]),
+    (Section::PlaylistSongs, Action::ToggleRepeat, &["w"]),
+    (Section::PlaylistSongs, Action::ToggleShuffle, &["e"]),
+    (Section::PlaylistSongs, Action::Grab, &["m"]),
+    (Section::PlaylistSongs, Action::Faster, &["+"]),
+    (Section::PlaylistSongs, Action::Slower, &["-"]),
+    (Section::PlaylistSongs, Action::Remove, &["d"]),
+    (Section::PlaylistSongs, Action::CycleMod, &["right"]),
+    (Section::PlaylistSongs, Action::DragUp, &["shift+up"]),
+    (Section::PlaylistSongs, Action::DragDown, &["shift+down"]),
+];
+
+/// extra keys the vim preset adds on top of the default ones, letters cant be used in search
+const VIM_KEYS: &[(Section, Action, &[&str])] = &[
+    (Section::Search, Action::Up, &["ctrl+k"]),
+    (Section::Search, Action::Down, &["ctrl+j"]),
+    (Section::PlayBar, Action::Up, &["k"]),
+    (Section::PlayBar, Action::Down, &["j"]),
+    (Section::PlayBar, Action::CycleMod, &["l"]),
+    (Section::Playlists, Action::Up, &["k"]),
+    (Section::Playlists, Action::Down, &["j"]),
+    (Section::Playlists, Action::Open, &["l"]),
+    (Section::PlaylistSongs, Action::Up, &["k"]),
+    (Section::PlaylistSongs, Action::Down, &["j"]),
+    (Section::PlaylistSongs, Action::Close, &["h"]),
+    (Section::PlaylistSongs, Action::CycleMod, &["l"]),
+    (Section::PlaylistSongs, Action::DragUp, &["K"]),
+    (Section::PlaylistSongs, Action::DragDown, &["J"]),
+];
+
+/// WHICH ACTION EACH KEY DOES IN EACH SECTION
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    bindings: Vec<(Section, KeyBinding, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::preset("default").unwrap()
+    }
+}
+
+impl Keymap {
+    pub fn preset(name: &str) -> Option<Self> {
+        let tables = match name {
+            "default" => vec![DEFAULT_KEYS],
+            "vim" => vec![DEFAULT_KEYS, VIM_KEYS],
+            _ => return None,
+        };
+        let mut bindings = Vec::new();
+        for (section, action, keys) in tables.into_iter().flatten() {
+            for key in keys.iter() {
+                // the presets are written by hand so a typo in one should show up straight away
+                let key = KeyBinding::parse(key).unwrap_or_else(|e| panic!("bad preset key: {}", e));
+                bindings.push((*section, key, *action));
+            }
+        }
+        Some(Self { bindings })
+    }
+
+    /// A MISSING FILE MEANS THE DEFAULT KEYS, A BAD OR CONFLICTING ONE IS AN ERROR
+    pub fn load(path: &str) -> Result<Self, String> {
+        let Some(config) = serialize::load_config::<KeyConfig>(path)? else {
+            return Ok(Self::default());
+        };
+
+        let preset = config.preset.as_deref().unwrap_or("default");
+        let mut keymap = Self::preset(preset).ok_or_else(|| format!("unknown key preset '{}'", preset))?;
+        for (section_name, actions) in config.sections {
+            let section = Section::from_name(&section_name)
+                .ok_or_else(|| format!("unknown key section '{}'", section_name))?;
+            for (action_name, keys) in actions {
+                let action = Action::from_name(&action_name)
+                    .ok_or_else(|| format!("unknown action '{}' in {}", action_name, section_name))?;
+                let keys = match keys {
+                    Keys::One(k) => vec![k],
+                    Keys::Many(k) => k,
+                };
+                let keys = keys.iter().map(|k| KeyBinding::parse(k)).collect::<Result<Vec<_>, _>>()?;
+                keymap.rebind(section, action, keys);
+            }
+        }
+
+        let conflicts = keymap.conflicts();
+        match conflicts.is_empty() {
+            true => Ok(keymap),
+            false => Err(format!("key conflicts: {}", conflicts.join(", "))),
+        }
+    }
+
+    /// an empty list of keys unbinds the action
+    fn rebind(&mut self, section: Section, action: Action, keys: Vec<KeyBinding>) {
+        self.bindings.retain(|(s, _, a)| !(*s == section && *a == action));
+        self.bindings.extend(keys.into_iter().map(|k| (section, k, action)));
+    }
+
+    /// KEYS THAT DO TWO THINGS IN THE SAME PLACE, HIDE A GLOBAL KEY, OR STOP A LETTER BEING TYPED IN SEARCH
+    fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        for (i, (section, key, action)) in self.bindings.iter().enumerate() {
+            for (other_section, other_key, other_action) in &self.bindings[i + 1..] {
+                if key == other_key && section == other_section && action != other_action {
+                    conflicts.push(format!(
+                        "'{}' is both {} and {} in {}", key, action.name(), other_action.name(), section.name()
+                    ));
+                }
+            }
+            if *section != Section::Global {
+                if let Some(global) = self.find(Section::Global, key) {
+                    conflicts.push(format!(
+                        "'{}' is {} in {} but {} everywhere", key, action.name(), section.name(), global.name()
+                    ));
+                }
+            }
+            if matches!(section, Section::Search | Section::Global) && key.is_typed() {
+                conflicts.push(format!("'{}' can't be typed in search while it's {}", key, action.name()));
+            }
+        }
+        conflicts
+    }
+
+    fn find(&self, section: Section, key: &KeyBinding) -> Option<Action> {
+        self.bindings.iter()
+            .find(|(s, k, _)| *s == section && k == key)
+            .map(|(_, _, a)| *a)
+    }
+
+    /// EVERY ACTION THAT WORKS IN THE SECTION WITH ITS KEYS, IN THE ORDER THEY WERE BOUND, THEN THE GLOBAL ONES
+    pub fn bindings(&self, section: Section) -> Vec<(Action, Vec<KeyBinding>)> {
+        let mut actions: Vec<(Action, Vec<KeyBinding>)> = Vec::new();
+        for target in [section, Section::Global] {
+            for (_, key, action) in self.bindings.iter().filter(|(s, _, _)| *s == target) {
+                match actions.iter_mut().find(|(a, _)| a == action) {
+                    Some((_, keys)) => keys.push(*key),
+                    None => actions.push((*action, vec![*key])),
+                }
+            }
+        }
+        actions
+    }
+
+    /// THE ACTION FOR A KEY PRESSED IN A SECTION, FALLING BACK TO THE GLOBAL KEYS
+    pub fn action(&self, section: Section, key: &KeyEvent) -> Option<Action> {
+        let key = KeyBinding::from_event(key);
+        self.find(section, &key).or_else(|| self.find(Section::Global, &key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(text: &str) -> KeyBinding {
+        KeyBinding::parse(text).unwrap()
+    }
+
+    #[test]
+    fn presets_have_no_conflicts() {
+        for name in ["default", "vim"] {
+            let keymap = Keymap::preset(name).unwrap();
+            assert_eq!(keymap.conflicts(), Vec::<String>::new(), "{}", name);
+        }
+        assert!(Keymap::preset("emacs").is_none());
+    }
+
+    #[test]
+    fn keys_with_modifiers_parse() {
+        assert_eq!(key("ctrl++"), KeyBinding::new(KeyCode::Char('+'), KeyModifiers::CONTROL));
+        assert_eq!(key("+"), KeyBinding::new(KeyCode::Char('+'), KeyModifiers::NONE));
+        assert_eq!(key("shift+up"), KeyBinding::new(KeyCode::Up, KeyModifiers::SHIFT));
+        assert_eq!(key("Ctrl+Alt+s"), KeyBinding::new(KeyCode::Char('s'), KeyModifiers::CONTROL | KeyModifiers::ALT));
+        assert_eq!(key("space"), KeyBinding::new(KeyCode::Char(' '), KeyModifiers::NONE));
+        assert_eq!(key("f12"), KeyBinding::new(KeyCode::F(12), KeyModifiers::NONE));
+        assert!(KeyBinding::parse("f13").is_err());
+        assert!(KeyBinding::parse("hyper+q").is_err());
+        assert!(KeyBinding::parse("ctrl+nothing").is_err());
+    }
+
+    #[test]
+    fn shift_is_part_of_letters() {
+        assert_eq!(key("Q"), key("shift+q"));
+        assert_eq!(key("Q"), key("shift+Q"));
+        assert_ne!(key("Q"), key("q"));
+        assert!(key("q").is_typed());
+        assert!(key("Q").is_typed());
+        assert!(!key("ctrl+q").is_typed());
+        // shift stays on keys that arent letters
+        assert_ne!(key("shift+up"), key("up"));
+    }
+
+    #[test]
+    fn keys_print_the_way_they_parse() {
+        for text in ["ctrl+s", "shift+up", "Q", "space", "f12", "pagedown", "ctrl++"] {
+            assert_eq!(key(text).to_string(), text);
+            assert_eq!(key(&key(text).to_string()), key(text));
+        }
+    }
+
+    #[test]
+    fn panels_cant_take_global_keys() {
+        let mut keymap = Keymap::default();
+        keymap.rebind(Section::PlayBar, Action::Up, vec![key("f1")]);
+        assert_eq!(keymap.conflicts(), vec!["'f1' is up in playbar but help everywhere"]);
+    }
+
+    #[test]
+    fn letters_cant_be_bound_in_search() {
+        let mut keymap = Keymap::default();
+        keymap.rebind(Section::Search, Action::Up, vec![key("k")]);
+        keymap.rebind(Section::Global, Action::Stats, vec![key("~")]);
+        assert_eq!(keymap.conflicts(), vec![
+            "'k' can't be typed in search while it's up",
+            "'~' can't be typed in search while it's stats",
+        ]);
+    }
+
+    #[test]
+    fn sections_fall_back_to_global_keys() {
+        let keymap = Keymap::default();
+        let event = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        assert_eq!(keymap.action(Section::PlayBar, &event(KeyCode::F(1))), Some(Action::Help));
+        assert_eq!(keymap.action(Section::Search, &event(KeyCode::Char('d'))), None);
+        let bindings = keymap.bindings(Section::Playlists);
+        assert!(bindings.iter().any(|(a, _)| *a == Action::Help));
+    }
+}
@@ -0,0 +1,475 @@
+use serde::Deserialize;
+use tui::layout::{Constraint, Direction, Layout, Rect};
+
+use crate::serialize;
+
+/// narrower than this and the columns are stacked on top of each other
+const STACK_BELOW: u16 = 100;
+
+/// how much room a panel or a column gets
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Size {
+    /// lines for a panel, characters for a column
+    Fixed(u16),
+    Percent(u16),
+    /// whatever is left over, shared between everything else that fills
+    Fill,
+}
+
+impl Size {
+    /// READS 8, "30%" OR "fill"
+    fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let size = match text.strip_suffix('%') {
+            _ if text == "fill" => Size::Fill,
+            Some(percent) => percent.trim().parse().ok().filter(|p| *p <= 100).map(Size::Percent)
+                .ok_or_else(|| format!("bad percentage '{}'", text))?,
+            None => text.parse().map(Size::Fixed).map_err(|_| format!("bad size '{}', use a number, a percentage or fill", text))?,
+        };
+        Ok(size)
+    }
+}
+
+/// THE CONSTRAINTS FOR SPLITTING A LENGTH BETWEEN THE SIZES
+/// fills are worked out here since the layout solver gives all the spare room to just one of them
+fn constraints(total: u16, sizes: &[Size]) -> Vec<Constraint> {
+    let taken: u16 = sizes.iter().map(|s| match s {
+        Size::Fixed(n) => *n,
+        Size::Percent(p) => (total as u32 * *p as u32 / 100) as u16,
+        Size::Fill => 0,
+    }).fold(0, u16::saturating_add);
+    let fills = sizes.iter().filter(|s| **s == Size::Fill).count().max(1) as u16;
+    let share = total.saturating_sub(taken) / fills;
+    sizes.iter().map(|s| match s {
+        Size::Fixed(n) => Constraint::Length(*n),
+        Size::Percent(p) => Constraint::Percentage(*p),
+        Size::Fill => Constraint::Min(share.max(1)),
+    }).collect()
+}
+
+/// the parts of the screen, each drawn in its own box
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Panel {
+    Search,
+    Songs,
+    Visualizer,
+    PlayBar,
+    Progress,
+    Info,
+    Playlists,
+}
+
+impl Panel {
+    pub const ALL: [Panel; 7] = [
+        Panel::Search,
+        Panel::Songs,
+        Panel::Visualizer,
+        Panel::PlayBar,
+        Panel::Progress,
+        Panel::Info,
+        Panel::Playlists,
+    ];
+
+    /// the name used for it in the layout file and the commands
+    pub fn name(&self) -> &'static str {
+        match self {
+            Panel::Search => "search",
+            Panel::Songs => "songs",
+            Panel::Visualizer => "visualizer",
+            Panel::PlayBar => "playbar",
+            Panel::Progress => "progress",
+            Panel::Info => "info",
+            Panel::Playlists => "playlists",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    /// how tall it is when stacked or maximized, a percentage of the whole screen would be too much
+    fn natural_height(&self) -> Size {
+        match self {
+            Panel::Search | Panel::Progress => Size::Fixed(3),
+            Panel::Info => Size::Fixed(9),
+            Panel::Visualizer => Size::Fixed(10),
+            Panel::Songs | Panel::PlayBar | Panel::Playlists => Size::Fill,
+        }
+    }
+
+    /// the search box is no use without its results, so the two are hidden and maximized together
+    fn group(&self) -> &'static [Panel] {
+        match self {
+            Panel::Search | Panel::Songs => &[Panel::Search, Panel::Songs],
+            Panel::Visualizer => &[Panel::Visualizer],
+            Panel::PlayBar => &[Panel::PlayBar],
+            Panel::Progress => &[Panel::Progress],
+            Panel::Info => &[Panel::Info],
+            Panel::Playlists => &[Panel::Playlists],
+        }
+    }
+}
+
+/// whether the columns are kept side by side
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Arrangement {
+    /// columns, until the terminal is too narrow for them
+    Auto,
+    Columns,
+    Stacked,
+}
+
+impl Arrangement {
+    pub const ALL: [Arrangement; 3] = [Arrangement::Auto, Arrangement::Columns, Arrangement::Stacked];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Arrangement::Auto => "auto",
+            Arrangement::Columns => "columns",
+            Arrangement::Stacked => "stacked",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.name() == name)
+    }
+}
+
+/// a size in the layout file, plain numbers dont need quoting
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SizeConfig {
+    Fixed(u16),
+    Text(String),
+}
+
+impl SizeConfig {
+    fn parse(&self) -> Result<Size, String> {
+        match self {
+            SizeConfig::Fixed(n) => Ok(Size::Fixed(*n)),
+            SizeConfig::Text(text) => Size::parse(text),
+        }
+    }
+}
+
+/// a panel in the layout file, the height can be left off to use the usual one
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PanelConfig {
+    Name(String),
+    Sized { panel: String, height: SizeConfig },
+}
+
+#[derive(Deserialize)]
+struct ColumnConfig {
+    #[serde(default)]
+    width: Option<SizeConfig>,
+    panels: Vec<PanelConfig>,
+}
+
+/// THE LAYOUT FILE, EVERYTHING IS OPTIONAL AND FALLS BACK TO HOW IT HAS ALWAYS LOOKED
+#[derive(Deserialize)]
+struct LayoutConfig {
+    #[serde(default)]
+    columns: Option<Vec<ColumnConfig>>,
+    #[serde(default)]
+    arrangement: Option<String>,
+    #[serde(default)]
+    stack_below: Option<u16>,
+    #[serde(default)]
+    hidden: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+struct Column {
+    width: Size,
+    panels: Vec<(Panel, Size)>,
+}
+
+/// WHICH PANELS GO WHERE AND HOW BIG, AND WHICH ARE HIDDEN OR MAXIMIZED RIGHT NOW
+#[derive(Clone, Debug)]
+pub struct PanelLayout {
+    columns: Vec<Column>,
+    pub arrangement: Arrangement,
+    stack_below: u16,
+    hidden: Vec<Panel>,
+    /// only the focused panel is drawn while this is set
+    pub maximized: bool,
+}
+
+impl Default for PanelLayout {
+    /// the 60/40 split things have always been
+    fn default() -> Self {
+        let column = |width, panels: &[(Panel, Size)]| Column { width, panels: panels.to_vec() };
+        Self {
+            columns: vec![
+                column(Size::Percent(60), &[
+                    (Panel::Search, Size::Fixed(3)),
+                    (Panel::Songs, Size::Fill),
+                    (Panel::Visualizer, Size::Fixed(10)),
+                ]),
+                column(Size::Percent(40), &[
+                    (Panel::PlayBar, Size::Percent(30)),
+                    (Panel::Progress, Size::Fixed(3)),
+                    (Panel::Info, Size::Fixed(9)),
+                    (Panel::Playlists, Size::Percent(70)),
+                ]),
+            ],
+            arrangement: Arrangement::Auto,
+            stack_below: STACK_BELOW,
+            hidden: Vec::new(),
+            maximized: false,
+        }
+    }
+}
+
+impl PanelLayout {
+    /// A MISSING FILE MEANS THE USUAL LAYOUT, A BAD ONE IS AN ERROR
+    pub fn load(path: &str) -> Result<Self, String> {
+        let Some(config) = serialize::load_config::<LayoutConfig>(path)? else {
+            return Ok(Self::default());
+        };
+        let panel = |name: &str| Panel::from_name(name).ok_or_else(|| format!("unknown panel '{}'", name));
+
+        let mut layout = Self::default();
+        if let Some(columns) = config.columns {
+            layout.columns = Vec::new();
+            for column in columns {
+                let width = column.width.as_ref().map_or(Ok(Size::Fill), SizeConfig::parse)?;
+                let panels = column.panels.iter().map(|p| match p {
+                    PanelConfig::Name(name) => panel(name).map(|p| (p, p.natural_height())),
+                    PanelConfig::Sized { panel: name, height } => Ok((panel(name)?, height.parse()?)),
+                }).collect::<Result<Vec<_>, String>>()?;
+                layout.columns.push(Column { width, panels });
+            }
+        }
+        // leaving a panel out would hide it for good, the hidden list is for that so it can be shown again
+        for p in Panel::ALL {
+            let count = layout.panels().filter(|(q, _)| *q == p).count();
+            if count != 1 {
+                return Err(format!("the layout needs {} once, not {} times", p.name(), count));
+            }
+        }
+        if let Some(arrangement) = config.arrangement {
+            layout.arrangement = Arrangement::from_name(&arrangement)
+                .ok_or_else(|| format!("unknown arrangement '{}'", arrangement))?;
+        }
+        layout.stack_below = config.stack_below.unwrap_or(STACK_BELOW);
+        for name in config.hidden {
+            layout.hide(panel(&name)?);
+        }
+        Ok(layout)
+    }
+
+    fn panels(&self) -> impl Iterator<Item = &(Panel, Size)> {
+        self.columns.iter().flat_map(|c| c.panels.iter())
+    }
+
+    pub fn is_hidden(&self, panel: Panel) -> bool {
+        self.hidden.contains(&panel)
+    }
+
+    pub fn hide(&mut self, panel: Panel) {
+        for p in panel.group() {
+            if !self.hidden.contains(p) {
+                self.hidden.push(*p);
+            }
+        }
+    }
+
+    pub fn show(&mut self, panel: Panel) {
+        self.hidden.retain(|p| !panel.group().contains(p));
+    }
+
+    pub fn show_all(&mut self) {
+        self.hidden.clear();
+    }
+
+    /// WHERE EACH PANEL GOES IN THE AREA, HIDDEN ONES ARE LEFT OUT
+    pub fn arrange(&self, area: Rect, focused: Panel, visualizer: bool) -> Vec<(Panel, Rect)> {
+        let shown = |panel: Panel| match self.maximized {
+            true => focused.group().contains(&panel),
+            false => !self.is_hidden(panel),
+        } && (visualizer || panel != Panel::Visualizer);
+
+        let stacked = match self.arrangement {
+            Arrangement::Auto => area.width < self.stack_below,
+            Arrangement::Columns => false,
+            Arrangement::Stacked => true,
+        };
+        // a maximized panel has the whole screen to itself so its fixed sizes are the only ones that matter
+        if stacked || self.maximized {
+            let panels: Vec<(Panel, Size)> = self.panels()
+                .filter(|(p, _)| shown(*p))
+                .map(|(p, _)| (*p, p.natural_height()))
+                .collect();
+            return split_column(area, &panels);
+        }
+
+        let columns: Vec<(Size, Vec<(Panel, Size)>)> = self.columns.iter()
+            .map(|c| (c.width, c.panels.iter().filter(|(p, _)| shown(*p)).copied().collect::<Vec<_>>()))
+            .filter(|(_, panels)| !panels.is_empty())
+            .collect();
+        let widths: Vec<Size> = columns.iter().map(|(width, _)| *width).collect();
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(constraints(area.width, &widths))
+            .split(area);
+        columns.iter().zip(chunks).flat_map(|((_, panels), chunk)| split_column(chunk, panels)).collect()
+    }
+}
+
+/// SPLITS ONE COLUMN BETWEEN ITS PANELS FROM THE TOP DOWN
+fn split_column(area: Rect, panels: &[(Panel, Size)]) -> Vec<(Panel, Rect)> {
+    let heights: Vec<Size> = panels.iter().map(|(_, size)| *size).collect();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(constraints(area.height, &heights))
+        .split(area);
+    panels.iter().map(|(p, _)| *p).zip(chunks).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(width: u16, height: u16) -> Rect {
+        Rect { x: 0, y: 0, width, height }
+    }
+
+    fn panels(arranged: &[(Panel, Rect)]) -> Vec<Panel> {
+        arranged.iter().map(|(p, _)| *p).collect()
+    }
+
+    fn find(arranged: &[(Panel, Rect)], panel: Panel) -> Rect {
+        arranged.iter().find(|(p, _)| *p == panel).map(|(_, r)| *r).unwrap()
+    }
+
+    /// writes the layout file and loads it back
+    fn load(name: &str, json: &str) -> Result<PanelLayout, String> {
+        let path = std::env::temp_dir().join(format!("sposu-layout-{}-{}.json", name, std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, json).unwrap();
+        let layout = PanelLayout::load(path);
+        std::fs::remove_file(path).unwrap();
+        layout
+    }
+
+    #[test]
+    fn sizes_parse() {
+        assert_eq!(Size::parse("8"), Ok(Size::Fixed(8)));
+        assert_eq!(Size::parse(" 30% "), Ok(Size::Percent(30)));
+        assert_eq!(Size::parse("fill"), Ok(Size::Fill));
+        assert!(Size::parse("101%").is_err());
+        assert!(Size::parse("big").is_err());
+    }
+
+    #[test]
+    fn fills_share_what_is_left() {
+        let shared = constraints(100, &[Size::Fixed(10), Size::Fill, Size::Percent(30), Size::Fill]);
+        assert_eq!(shared, vec![Constraint::Length(10), Constraint::Min(30), Constraint::Percentage(30), Constraint::Min(30)]);
+        // fills never go to nothing even with no room left
+        assert_eq!(constraints(10, &[Size::Fixed(20), Size::Fill]), vec![Constraint::Length(20), Constraint::Min(1)]);
+
+        let chunks = split_column(area(40, 100), &[(Panel::Search, Size::Fill), (Panel::Songs, Size::Fixed(10)), (Panel::Info, Size::Fill)]);
+        assert_eq!(chunks.iter().map(|(_, r)| r.height).collect::<Vec<_>>(), vec![45, 10, 45]);
+    }
+
+    #[test]
+    fn the_default_layout_is_two_columns() {
+        let layout = PanelLayout::default();
+        let arranged = layout.arrange(area(200, 60), Panel::Songs, true);
+        assert_eq!(arranged.len(), Panel::ALL.len());
+        assert_eq!(find(&arranged, Panel::Search), Rect { x: 0, y: 0, width: 120, height: 3 });
+        assert_eq!(find(&arranged, Panel::PlayBar).x, 120);
+        assert!(!panels(&layout.arrange(area(200, 60), Panel::Songs, false)).contains(&Panel::Visualizer));
+    }
+
+    #[test]
+    fn narrow_terminals_stack_the_columns() {
+        let layout = PanelLayout::default();
+        let arranged = layout.arrange(area(99, 80), Panel::Songs, true);
+        assert_eq!(panels(&arranged), Panel::ALL.to_vec());
+        assert!(arranged.iter().all(|(_, r)| r.x == 0 && r.width == 99));
+        assert_eq!(find(&arranged, Panel::Search).height, 3);
+        assert_eq!(find(&arranged, Panel::Info).height, 9);
+
+        let columns = PanelLayout { arrangement: Arrangement::Columns, ..PanelLayout::default() };
+        assert_eq!(find(&columns.arrange(area(99, 80), Panel::Songs, true), Panel::PlayBar).x, 59);
+        let stacked = PanelLayout { arrangement: Arrangement::Stacked, ..PanelLayout::default() };
+        assert_eq!(find(&stacked.arrange(area(200, 80), Panel::Songs, true), Panel::PlayBar).x, 0);
+    }
+
+    #[test]
+    fn maximizing_keeps_search_with_its_songs() {
+        let layout = PanelLayout { maximized: true, ..PanelLayout::default() };
+        let arranged = layout.arrange(area(200, 60), Panel::Search, true);
+        assert_eq!(arranged, vec![
+            (Panel::Search, Rect { x: 0, y: 0, width: 200, height: 3 }),
+            (Panel::Songs, Rect { x: 0, y: 3, width: 200, height: 57 }),
+        ]);
+        let arranged = layout.arrange(area(200, 60), Panel::Playlists, true);
+        assert_eq!(arranged, vec![(Panel::Playlists, area(200, 60))]);
+    }
+
+    #[test]
+    fn hiding_goes_by_group_and_empty_columns_go() {
+        let mut layout = PanelLayout::default();
+        layout.hide(Panel::Songs);
+        assert!(layout.is_hidden(Panel::Search));
+        layout.hide(Panel::Visualizer);
+        let arranged = layout.arrange(area(200, 60), Panel::PlayBar, true);
+        assert_eq!(panels(&arranged), vec![Panel::PlayBar, Panel::Progress, Panel::Info, Panel::Playlists]);
+        assert!(arranged.iter().all(|(_, r)| r.width == 200));
+
+        layout.show(Panel::Search);
+        assert!(!layout.is_hidden(Panel::Songs));
+        layout.show_all();
+        assert_eq!(layout.arrange(area(200, 60), Panel::PlayBar, true).len(), Panel::ALL.len());
+    }
+
+    #[test]
+    fn layout_files_load() {
+        let layout = load("ok", r#"{
+            "columns": [
+                {"width": 30, "panels": ["playlists", {"panel": "info", "height": "fill"}]},
+                {"panels": ["search", "songs", {"panel": "visualizer", "height": 12}]},
+                {"width": "25%", "panels": ["playbar", "progress"]}
+            ],
+            "arrangement": "columns",
+            "stack_below": 60,
+            "hidden": ["progress"]
+        }"#).unwrap();
+        assert_eq!(layout.arrangement, Arrangement::Columns);
+        assert!(layout.is_hidden(Panel::Progress));
+        let arranged = layout.arrange(area(200, 60), Panel::Songs, true);
+        assert_eq!(find(&arranged, Panel::Playlists), Rect { x: 0, y: 0, width: 30, height: 30 });
+        assert_eq!(find(&arranged, Panel::Search).x, 30);
+        assert_eq!(find(&arranged, Panel::Visualizer).height, 12);
+        assert_eq!(find(&arranged, Panel::PlayBar), Rect { x: 150, y: 0, width: 50, height: 60 });
+
+        // only the columns were left out, so the rest is still the usual layout
+        let layout = load("partial", r#"{"arrangement": "stacked"}"#).unwrap();
+        assert_eq!(layout.arrangement, Arrangement::Stacked);
+        assert_eq!(layout.columns.len(), 2);
+
+        let missing = std::env::temp_dir().join("sposu-layout-missing.json");
+        assert!(PanelLayout::load(missing.to_str().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn every_panel_is_needed_once() {
+        let all_but = |left_out: &str| {
+            let names: Vec<String> = Panel::ALL.iter().map(|p| p.name()).filter(|n| *n != left_out).map(|n| format!("\"{}\"", n)).collect();
+            names.join(", ")
+        };
+        let missing = load("missing", &format!(r#"{{"columns": [{{"panels": [{}]}}]}}"#, all_but("info")));
+        assert_eq!(missing.unwrap_err(), "the layout needs info once, not 0 times");
+        let twice = load("twice", &format!(r#"{{"columns": [{{"panels": [{}, "songs"]}}]}}"#, all_but("")));
+        assert_eq!(twice.unwrap_err(), "the layout needs songs once, not 2 times");
+
+        assert_eq!(load("panel", r#"{"hidden": ["sidebar"]}"#).unwrap_err(), "unknown panel 'sidebar'");
+        assert_eq!(load("arrangement", r#"{"arrangement": "grid"}"#).unwrap_err(), "unknown arrangement 'grid'");
+        assert!(load("json", "{").is_err());
+    }
+}
@@ -0,0 +1,33 @@
+use std::collections::HashMap;
+
+use crate::osu::{Song, SongId};
+
+/// OWNS EVERY SONG, EVERYTHING ELSE REFERS TO THEM BY ID
+pub struct Library {
+    songs: Vec<Song>,
+    positions: HashMap<SongId, usize>,
+}
+
+impl Library {
+    pub fn new(songs: Vec<Song>) -> Self {
+        let positions = songs.iter().enumerate().map(|(i, s)| (s.id.clone(), i)).collect();
+        Self { songs, positions }
+    }
+
+    pub fn songs(&self) -> &[Song] {
+        &self.songs
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Song> {
+        self.positions.get(id).map(|i| &self.songs[*i])
+    }
+
+    pub fn find_by_path(&self, audio_path: &str) -> Option<&Song> {
+        self.songs.iter().find(|s| s.audio_path == audio_path)
+    }
+
+    /// SWAPS IN A FRESH SCAN, ANYTHING HOLDING IDS STILL POINTS AT THE SAME SONGS
+    pub fn replace(&mut self, songs: Vec<Song>) {
+        *self = Library::new(songs);
+    }
+}
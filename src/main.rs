@@ -12,23 +12,47 @@ mod renderer;
 mod app;
 mod serialize;
 mod player;
+mod library;
+mod visualizer;
+mod click;
+mod export;
+mod playlist_files;
+mod query;
+mod session;
+mod stats;
+mod user_data;
+mod undo;
+mod keymap;
+mod command;
+mod theme;
+mod layout;
 
 use app::*;
 use osu::Song;
+use library::Library;
 use player::deserialize_playlist;
 
-fn load_songs(data: &mut AppData) -> Vec<Song> {
+/// how long to wait for input before redrawing, this is also the visualizer refresh rate
+pub const POLL_RATE: Duration = Duration::from_millis(20);
+
+fn load_songs(data: &mut AppData) -> io::Result<Vec<Song>> {
     if data.serialized_songs {
-        let pot_data = serialize::deserialize(&data.serialize_path);
-        if pot_data.is_ok() {
-            return pot_data.unwrap();
+        let pot_data: Result<Vec<Song>, _> = serialize::deserialize(&data.serialize_path);
+        // songs cached before ids, beatmap paths and timing points existed have to be scanned again,
+        // every scanned song has a beatmap path so an empty one means the timing points were never read
+        if let Ok(songs) = pot_data {
+            if songs.iter().all(|s| !s.id.is_empty() && !s.beatmap_path.is_empty()) {
+                return Ok(songs);
+            }
         }
     }
-    let songs = osu::load_all_songs(&data.song_path);
-    serialize::serialize(&songs, &data.serialize_path);
-    data.serialized_songs = true;
-    serialize::serialize(data, "assets/data.json");
-    songs
+    let songs = osu::load_all_songs(&data.song_path)?;
+    // the cache only makes startup faster so failing to write it isnt a big deal
+    if serialize::serialize(&songs, &data.serialize_path).is_ok() {
+        data.serialized_songs = true;
+        let _ = serialize::serialize(data, "assets/data.json");
+    }
+    Ok(songs)
 }
 
 /// INITIALISES APP
@@ -44,11 +68,33 @@ fn main() -> Result<(), io::Error>{
     let mut terminal = Terminal::new(backend)?;
 
     let mut global_data: AppData = serialize::deserialize::<AppData>("assets/data.json").unwrap();
-    let songs = load_songs(&mut global_data);
-    let playlists = deserialize_playlist(&global_data.playlist_path.clone()).unwrap();
+    // an unreadable song folder starts with an empty library instead of crashing, a rescan picks it up once fixed
+    let (songs, songs_error) = match load_songs(&mut global_data) {
+        Ok(songs) => (songs, None),
+        Err(e) => (Vec::new(), Some(format!("couldn't read {}: {}", global_data.song_path, e))),
+    };
+    let library = Library::new(songs);
+    let (playlists, unresolved, playlist_error) = match deserialize_playlist(&global_data.playlist_path.clone(), &library) {
+        Ok((playlists, unresolved)) => (playlists, unresolved, None),
+        Err(e) => (Vec::new(), Vec::new(), Some(e)),
+    };
 
-    let app = App::new(songs, global_data, playlists);
-    let res = main_loop(&mut terminal, app);
+    let mut app = App::new(library, global_data, playlists);
+    app.restore_session();
+    if !unresolved.is_empty() {
+        app.status = format!("{} playlist songs not found: {}", unresolved.len(), unresolved.join(", "));
+    }
+    if let Some(e) = songs_error {
+        app.status = e;
+    }
+    // saving the empty list over a file that couldnt be read would lose every playlist in it
+    if let Some(e) = playlist_error {
+        app.playlists_unreadable(e);
+    }
+    let res = main_loop(&mut terminal, &mut app);
+    app.record_current_play();
+    app.save_playlists();
+    app.save_session();
     
     disable_raw_mode()?;
     execute!(terminal.backend_mut(), LeaveAlternateScreen, DisableMouseCapture)?;
@@ -61,17 +107,22 @@ fn main() -> Result<(), io::Error>{
 }
 
 /// RUNS THE APP
-fn main_loop<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<()> {
+fn main_loop<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<()> {
     loop {
-        terminal.draw(|f| renderer::render(f, &app))?;
+        terminal.draw(|f| app.areas = renderer::render(f, app))?;
+        app.poll_status();
+        app.save_if_changed();
         if app.player.sink.empty() {
-            app.player.try_new_song()
+            app.player.try_new_song(&app.library, &mut app.playlists)
         }
-        if !crossterm::event::poll(Duration::from_millis(20))? {
+        if !crossterm::event::poll(POLL_RATE)? {
             continue;
         }
-        if let Event::Key(key) = event::read()? {
-            app.event_handler(&key).unwrap();
+        match event::read()? {
+            // an error means the user wants to quit
+            Event::Key(key) if app.event_handler(&key).is_err() => return Ok(()),
+            Event::Mouse(mouse) => app.mouse_handler(&mouse),
+            _ => {}
         }
     }
 }
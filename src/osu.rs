@@ -1,63 +1,256 @@
-use std::{fs::{self, File}, io::BufReader};
+use std::{fs::{self, File}, io::{self, BufReader}};
 use serde::{Serialize, Deserialize};
 
-use libosu::prelude::Beatmap;
+use libosu::prelude::{Beatmap, TimingPointKind};
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub enum Mod {
+    #[default]
     NoMod,
     Nightcore,
     DoubleTime,
 }
 
+impl Mod {
+    /// how much faster the song is played with the mod on
+    pub fn speed(&self) -> f32 {
+        match self {
+            Mod::NoMod => 1.0,
+            _ => 1.5,
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            Mod::NoMod => Mod::DoubleTime,
+            Mod::DoubleTime => Mod::Nightcore,
+            Mod::Nightcore => Mod::NoMod,
+        }
+    }
+
+    pub fn short_name(&self) -> &'static str {
+        match self {
+            Mod::NoMod => "NM",
+            Mod::DoubleTime => "DT",
+            Mod::Nightcore => "NC",
+        }
+    }
+
+    /// THE MOD FROM ITS SHORT NAME IN EITHER CASE
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Mod::NoMod, Mod::DoubleTime, Mod::Nightcore].into_iter().find(|m| m.short_name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// stable identity of a song, made from the beatmap set id and the audio path
+/// so it stays the same when the song folder is scanned again
+pub type SongId = String;
+
+pub fn song_id(beatmap_set_id: i32, audio_path: &str) -> SongId {
+    format!("{}:{}", beatmap_set_id, audio_path.replace('\\', "/"))
+}
+
+/// slowest and fastest custom rate an entry can be set to
+const MIN_RATE: f32 = 0.5;
+const MAX_RATE: f32 = 2.0;
+
+/// a song in a playlist or the playbar, the mod belongs to the entry
+/// so the same song can be in a playlist as both NM and NC
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: SongId,
+    #[serde(default)]
+    pub modifier: Mod,
+    /// overrides the speed of the mod when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate: Option<f32>,
+}
+
+impl Entry {
+    pub fn new(id: SongId, modifier: Mod) -> Self {
+        Self { id, modifier, rate: None }
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.rate.unwrap_or(self.modifier.speed())
+    }
+
+    /// SHORT LABEL LIKE "NC" OR "1.25x"
+    pub fn tag(&self) -> String {
+        match self.rate {
+            Some(rate) => format!("{}x", rate),
+            None => String::from(self.modifier.short_name()),
+        }
+    }
+
+    /// picking a mod throws away any custom rate
+    pub fn cycle_mod(&mut self) {
+        self.modifier = self.modifier.next();
+        self.rate = None;
+    }
+
+    /// NUDGES THE RATE FROM WHATEVER SPEED IT IS PLAYING AT
+    pub fn change_rate(&mut self, step: f32) {
+        let rate = ((self.speed() + step) * 100.0).round() / 100.0;
+        self.rate = Some(rate.clamp(MIN_RATE, MAX_RATE));
+    }
+}
+
+/// a timing point from the beatmap, inherited ones take the beat length of the last uninherited one
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct TimingPoint {
+    pub time: i32,
+    pub beat_length: f64,
+    pub meter: u32,
+    pub kiai: bool,
+    pub uninherited: bool,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Song {
+    #[serde(default)]
+    pub id: SongId,
     pub audio_path: String,
     pub song_name: String,
     pub artist: String,
-    pub modifier: Mod,
     pub length: u64,
+    #[serde(default)]
+    pub beatmap_path: String,
+    #[serde(default)]
+    pub timing_points: Vec<TimingPoint>,
 }
 
 impl Song {
-    fn new(beatmap: Beatmap, audio_path: String, length: u64) -> Self {
+    fn new(beatmap: Beatmap, audio_path: String, beatmap_path: String, length: u64) -> Self {
+        let timing_points = convert_timing_points(&beatmap);
         Self { 
+            id: song_id(beatmap.beatmap_set_id, &audio_path),
             audio_path,
             song_name: beatmap.title,
             artist: beatmap.artist,
-            modifier: Mod::NoMod,
-            length
+            length,
+            beatmap_path,
+            timing_points,
+        }
+    }
+
+    /// THE TIMING POINT WHICH IS ACTIVE AT THE GIVEN TIME
+    pub fn timing_at(&self, ms: f64) -> Option<&TimingPoint> {
+        let mut current = self.timing_points.first();
+        for point in &self.timing_points {
+            if point.time as f64 > ms {
+                break;
+            }
+            current = Some(point);
         }
+        current
     }
+
+    /// THE BPM AT THE GIVEN TIME, WITHOUT THE MOD APPLIED
+    pub fn bpm_at(&self, ms: f64) -> Option<f64> {
+        self.timing_at(ms).map(|p| 60000.0 / p.beat_length)
+    }
+
+    /// THE BPM WHICH IS ACTIVE FOR THE LONGEST, WITHOUT THE MOD APPLIED
+    pub fn main_bpm(&self) -> Option<f64> {
+        let end = (self.length * 1000) as i32;
+        let mut durations: Vec<(f64, i32)> = Vec::new();
+        for (i, point) in self.timing_points.iter().enumerate() {
+            let next = self.timing_points.get(i + 1).map_or(end, |p| p.time);
+            let bpm = (60000.0 / point.beat_length).round();
+            match durations.iter_mut().find(|(b, _)| *b == bpm) {
+                Some((_, d)) => *d += next - point.time,
+                None => durations.push((bpm, next - point.time)),
+            }
+        }
+        durations.iter().max_by_key(|(_, d)| *d).map(|(b, _)| *b)
+    }
+
+    /// HOW FAR THROUGH THE CURRENT BEAT THE GIVEN TIME IS, FROM 0 TO 1
+    pub fn beat_progress(&self, ms: f64) -> Option<f64> {
+        let point = self.timing_at(ms)?;
+        let since = (ms - point.time as f64).max(0.0);
+        Some((since % point.beat_length) / point.beat_length)
+    }
+
+    /// ALL THE KIAI SECTIONS AS START AND END TIMES IN MS
+    pub fn kiai_sections(&self) -> Vec<(i32, i32)> {
+        let mut sections = Vec::new();
+        let mut start = None;
+        for point in &self.timing_points {
+            match (point.kiai, start) {
+                (true, None) => start = Some(point.time),
+                (false, Some(s)) => {
+                    sections.push((s, point.time));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(s) = start {
+            sections.push((s, (self.length * 1000) as i32));
+        }
+        sections
+    }
+}
+
+/// TURNS THE LIBOSU TIMING POINTS INTO ONES WHICH CAN BE SERIALIZED
+fn convert_timing_points(beatmap: &Beatmap) -> Vec<TimingPoint> {
+    let mut points = Vec::new();
+    let mut beat_length = 500.0;
+    let mut meter = 4;
+    for point in &beatmap.timing_points {
+        let uninherited = match &point.kind {
+            TimingPointKind::Uninherited(info) => {
+                beat_length = info.mpb;
+                meter = info.meter;
+                true
+            }
+            TimingPointKind::Inherited(_) => false,
+        };
+        // broken maps can have negative or zero beat lengths
+        if beat_length <= 0.0 {
+            continue;
+        }
+        points.push(TimingPoint {
+            time: point.time.0,
+            beat_length,
+            meter,
+            kiai: point.kiai,
+            uninherited,
+        });
+    }
+    points
 }
 
 /// LOADS ALL THE SONGS IN THE SONG FOLDER PROVIDED
-/// IT ERRORS ON SOME OF THE FILES WHICH IS BAD BUT IDK HOW TO FIX IT
-pub fn load_all_songs(song_path: &str) -> Vec<Song> {
+/// only the song folder itself not being readable is an error, beatmaps that cant be read are skipped
+pub fn load_all_songs(song_path: &str) -> io::Result<Vec<Song>> {
     let mut songs = Vec::new();
-    let song_folder = fs::read_dir(song_path).unwrap();
+    let song_folder = fs::read_dir(song_path)?;
     let mut used_songs = Vec::new();
 
     // for every folder in the directory
-    for folder in song_folder {
-        let folder = folder.unwrap();
-        if !folder.metadata().unwrap().is_dir() {
+    for folder in song_folder.filter_map(|f| f.ok()) {
+        if !folder.metadata().is_ok_and(|m| m.is_dir()) {
             continue;
         }
-        let valid_folder = fs::read_dir(folder.path().as_path()).unwrap();
-        let mp3_folder = fs::read_dir(folder.path().as_path()).unwrap();
+        let (valid_folder, mp3_folder) = match (fs::read_dir(folder.path()), fs::read_dir(folder.path())) {
+            (Ok(valid), Ok(mp3)) => (valid, mp3),
+            _ => continue,
+        };
 
         let mut mp3_ratio = 0;
-        for file in mp3_folder {
-            if let Some(e) = file.unwrap().path().extension() {
+        for file in mp3_folder.filter_map(|f| f.ok()) {
+            if let Some(e) = file.path().extension() {
                 if e == "mp3" {
                     mp3_ratio += 1;
                 }
             }
         }
         
-        for file in valid_folder {
-            let file = file.unwrap();
+        for file in valid_folder.filter_map(|f| f.ok()) {
             if let Some(e) = file.path().extension() {
                 if e.to_string_lossy() != "osu" {
                     continue;
@@ -66,7 +259,10 @@ pub fn load_all_songs(song_path: &str) -> Vec<Song> {
                 continue;
             };
 
-            let osu_file_reader = BufReader::new(File::open(&file.path()).unwrap());    
+            let osu_file_reader = match File::open(file.path()) {
+                Ok(f) => BufReader::new(f),
+                Err(_) => continue,
+            };
             if let Ok(i) = Beatmap::parse(osu_file_reader) {
                 // dont add duplicate songs.
                 let name_artist = format!("{}-{}", i.title, i.artist);
@@ -76,7 +272,6 @@ pub fn load_all_songs(song_path: &str) -> Vec<Song> {
                 used_songs.push(name_artist);
 
                 let audio_path = format!("{}/{}", folder.path().to_string_lossy(), &i.audio_filename);
-                println!("{}", audio_path);
                 let length = match mp3_duration::from_path(&audio_path) {
                     Ok(l) => l.as_secs(),
                     Err(e) => e.at_duration.as_secs(),
@@ -84,7 +279,8 @@ pub fn load_all_songs(song_path: &str) -> Vec<Song> {
 
                 // remove the parent path to use the global path when loaded
                 let path = audio_path.replace(song_path, "");
-                songs.push(Song::new(i, path, length));
+                let beatmap_path = file.path().to_string_lossy().replace(song_path, "");
+                songs.push(Song::new(i, path, beatmap_path, length));
                 mp3_ratio -= 1;
                 if mp3_ratio == 0 {
                     break;
@@ -92,5 +288,25 @@ pub fn load_all_songs(song_path: &str) -> Vec<Song> {
             }
         }
     }
-    songs
-}
\ No newline at end of file
+    Ok(songs)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_missing_song_folder_is_an_error() {
+        let path = std::env::temp_dir().join(format!("sposu_missing_songs_{}", std::process::id()));
+        assert!(load_all_songs(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn folders_without_beatmaps_are_skipped() {
+        let path = std::env::temp_dir().join(format!("sposu_empty_songs_{}", std::process::id()));
+        fs::create_dir_all(path.join("123 empty")).unwrap();
+        fs::write(path.join("not a folder.txt"), "").unwrap();
+        let songs = load_all_songs(path.to_str().unwrap());
+        fs::remove_dir_all(&path).unwrap();
+        assert!(songs.unwrap().is_empty());
+    }
+}
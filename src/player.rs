@@ -1,261 +1,933 @@
-use std::fs::File;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::time::Duration;
 
-use rand::Rng;
-use rodio::{Sink, OutputStream, OutputStreamHandle};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rodio::{Sink, OutputStream, OutputStreamHandle, Decoder, Source};
 use serde::{Deserialize, Serialize};
-use crate::{osu::{Song, Mod}, serialize::{deserialize, self}};
-
-/// where the playbar is getting its songs from
-/// NONE => not playing rn
-/// PLAYLIST => from playlist
-/// NORMAL => from search part
-pub enum PlaybarSource {
-    None,
-    Playlist,
-    Normal,
+use crate::{osu::{Song, Entry, Mod}, serialize::{deserialize, self}};
+use crate::library::Library;
+use crate::visualizer::{SharedBuffer, Tap, new_buffer};
+use crate::click::{ClickMode, ClickTrack};
+use crate::query::{Query, QueryContext};
+use crate::stats::{self, Play};
+
+/// how far into a song previous restarts it instead of going back
+const RESTART_SECONDS: f32 = 3.0;
+
+/// what the playbar panel is showing
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum QueueView {
+    UpNext,
+    History,
 }
 
-pub struct Player<'a> {
+/// a line in the playbar panel, pointing at where its song lives
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum QueueRow {
+    Playing,
+    /// index into the queue
+    Queued(usize),
+    /// index into the songs the loaded playlist was started with
+    Playlist(usize),
+    /// index into the history, most recent last
+    History(usize),
+}
+
+pub struct Player {
     // rodio
     pub sink: Sink,
-    pub _stream: OutputStream,
-    pub stream_handle: OutputStreamHandle,
+    /// none for a player without sound, which is only made for the tests
+    pub _stream: Option<OutputStream>,
+    pub stream_handle: Option<OutputStreamHandle>,
+    pub samples: SharedBuffer,
 
     // playbar
-    pub playbar_source: PlaybarSource,
-    pub current_songs: Vec<&'a Song>,
-    pub current_playlist: Option<&'a Playlist<'a>>,
+    pub playing: Option<Entry>,
+    /// songs added by hand, these play before the loaded playlist carries on
+    pub queue: Vec<Entry>,
+    /// songs which have finished, most recent last
+    pub history: Vec<Entry>,
+    pub current_playlist: Option<usize>,
+    pub playing_playlist: Option<usize>,
+    pub view: QueueView,
     pub hovered_index: usize,
     pub parent_path: String,
-    pub playing_index: usize,
     pub volume: f32,
     pub is_playing: bool,
+    pub click_mode: ClickMode,
+    pub repeat_one: bool,
+    // set when the playing song is cut off so repeat one doesnt play it again
+    skipping: bool,
+    // when the playing song started and where from, for the play history
+    listen_start: Option<(u64, Duration)>,
+    // how much was heard before the last seek, the listen carries on from the new position
+    listened: Duration,
+    /// plays which have ended and are waiting to be saved
+    pub plays: Vec<Play>,
 }
 
-impl Player<'_> {
+impl Player {
     pub fn new(parent_path: String) -> Self {
-        let (_stream, stream_handle) = OutputStream::try_default().unwrap();
+        let (stream, stream_handle) = OutputStream::try_default().unwrap();
+        Self::with_output(parent_path, Some(stream), Some(stream_handle))
+    }
+
+    // test machines often have no sound card, everything but playing works without one
+    #[cfg(test)]
+    pub fn silent() -> Self {
+        Self::with_output(String::new(), None, None)
+    }
+
+    fn with_output(parent_path: String, _stream: Option<OutputStream>, stream_handle: Option<OutputStreamHandle>) -> Self {
         let sink = Sink::new_idle().0;
-        let currently_playing = PlaybarSource::None;
         Self {
             sink,
             _stream,
             stream_handle,
-            playbar_source: currently_playing,
-            current_songs: Vec::new(),
+            samples: new_buffer(),
+            playing: None,
+            queue: Vec::new(),
+            history: Vec::new(),
             current_playlist: None,
+            playing_playlist: None,
+            view: QueueView::UpNext,
             hovered_index: 0,
             parent_path,
-            playing_index: 0,
             volume: 0.5,
-            is_playing: false
+            is_playing: false,
+            click_mode: ClickMode::Off,
+            repeat_one: false,
+            skipping: false,
+            listen_start: None,
+            listened: Duration::ZERO,
+            plays: Vec::new(),
         }
     }
 
-    // removes the hovered song from the playbar
-    pub fn remove_hovered_song(&mut self) {
-        // the playing song is automatically removed if the sink stops
-        if self.playing_index == self.hovered_index {
-            self.sink.stop();
-            // playlist songs have to be manually removed
-            if let PlaybarSource::Playlist = self.playbar_source {
-                self.current_playlist.as_mut().unwrap().remove_playbar_song(self.hovered_index);
-                self.current_songs.remove(self.hovered_index);
+    // the entry which is currently in the sink
+    pub fn playing_entry(&self) -> Option<&Entry> {
+        if !self.is_playing || self.sink.empty() {
+            return None;
+        }
+        self.playing.as_ref()
+    }
+
+    // how far into the playing song we are, in the songs own time
+    pub fn position(&self) -> Duration {
+        match self.samples.lock() {
+            Ok(b) => b.position(),
+            Err(_) => Duration::ZERO,
+        }
+    }
 
+    // every line of the playbar panel for the current view
+    pub fn rows(&self, playlists: &[Playlist]) -> Vec<QueueRow> {
+        match self.view {
+            QueueView::UpNext => {
+                let mut rows = Vec::new();
+                if self.playing.is_some() {
+                    rows.push(QueueRow::Playing);
+                }
+                rows.extend((0..self.queue.len()).map(QueueRow::Queued));
+                if let Some(p) = self.playing_playlist.and_then(|i| playlists.get(i)) {
+                    rows.extend(p.upcoming().into_iter().map(QueueRow::Playlist));
+                }
+                rows
             }
-        } else {
-            self.current_playlist.as_mut().unwrap().remove_playbar_song(self.hovered_index);
-            self.current_songs.remove(self.hovered_index);
+            QueueView::History => (0..self.history.len()).rev().map(QueueRow::History).collect(),
+        }
+    }
+
+    pub fn row_entry<'a>(&'a self, row: QueueRow, playlists: &'a [Playlist]) -> Option<&'a Entry> {
+        match row {
+            QueueRow::Playing => self.playing.as_ref(),
+            QueueRow::Queued(i) => self.queue.get(i),
+            QueueRow::Playlist(i) => self.playing_playlist.and_then(|p| playlists.get(p))?.choice(i),
+            QueueRow::History(i) => self.history.get(i),
         }
+    }
 
-        if self.hovered_index == self.current_songs.len() {
-            self.hovered_index -= 1;
+    fn row_entry_mut<'a>(&'a mut self, row: QueueRow, playlists: &'a mut [Playlist]) -> Option<&'a mut Entry> {
+        match row {
+            QueueRow::Playing => self.playing.as_mut(),
+            QueueRow::Queued(i) => self.queue.get_mut(i),
+            QueueRow::Playlist(i) => self.playing_playlist.and_then(|p| playlists.get_mut(p))?.choice_mut(i),
+            QueueRow::History(i) => self.history.get_mut(i),
         }
     }
 
-    // removes the playlist from the whole struct
-    pub fn pop_current_playlist(&mut self) -> &Playlist {
-        self.playbar_source = PlaybarSource::None;
-        let temp = self.current_playlist.unwrap();
+    pub fn hovered_row(&self, playlists: &[Playlist]) -> Option<QueueRow> {
+        self.rows(playlists).get(self.hovered_index).copied()
+    }
+
+    pub fn hovered_entry<'a>(&'a self, playlists: &'a [Playlist]) -> Option<&'a Entry> {
+        self.row_entry(self.hovered_row(playlists)?, playlists)
+    }
+
+    // moves the hover, wrapping round at either end
+    pub fn move_hover(&mut self, playlists: &[Playlist], up: bool) {
+        let count = self.rows(playlists).len();
+        if count == 0 {
+            return;
+        }
+        self.hovered_index = match up {
+            true if self.hovered_index == 0 => count - 1,
+            true => self.hovered_index - 1,
+            false => (self.hovered_index + 1) % count,
+        };
+    }
+
+    // keeps the hover on a line that exists after something is removed
+    pub fn clamp_hover(&mut self, playlists: &[Playlist]) {
+        let count = self.rows(playlists).len();
+        if self.hovered_index >= count {
+            self.hovered_index = count.saturating_sub(1);
+        }
+    }
+
+    pub fn toggle_view(&mut self) {
+        self.view = match self.view {
+            QueueView::UpNext => QueueView::History,
+            QueueView::History => QueueView::UpNext,
+        };
+        self.hovered_index = 0;
+    }
+
+    // removes the hovered song from the playbar, removing the playing one skips it
+    pub fn remove_hovered_song(&mut self, playlists: &mut [Playlist]) {
+        match self.hovered_row(playlists) {
+            Some(QueueRow::Playing) => self.skip(),
+            Some(QueueRow::Queued(i)) => {
+                self.queue.remove(i);
+            }
+            // only the playbars copy of the playlist loses the song
+            Some(QueueRow::Playlist(i)) => {
+                if let Some(p) = self.playing_playlist.and_then(|p| playlists.get_mut(p)) {
+                    p.remove_playbar_song(i);
+                }
+            }
+            Some(QueueRow::History(i)) => {
+                self.history.remove(i);
+            }
+            None => return,
+        }
+        self.clamp_hover(playlists);
+    }
+
+    // keeps the playlist indexes pointing at the same playlists after one is inserted
+    pub fn playlist_inserted(&mut self, index: usize) {
+        for i in [&mut self.current_playlist, &mut self.playing_playlist].into_iter().flatten() {
+            if *i >= index {
+                *i += 1;
+            }
+        }
+    }
+
+    // keeps the playlist indexes pointing at the same playlists after one is removed
+    pub fn playlist_removed(&mut self, index: usize) {
+        if self.playing_playlist == Some(index) {
+            self.unload_playbar_playlist();
+        }
+        if self.current_playlist == Some(index) {
+            self.current_playlist = None;
+        }
+        for i in [&mut self.current_playlist, &mut self.playing_playlist].into_iter().flatten() {
+            if *i > index {
+                *i -= 1;
+            }
+        }
+    }
+
+    // stops showing the songs of the opened playlist
+    pub fn close_playlist(&mut self) {
         self.current_playlist = None;
-        &temp
     }
 
-    // loads the playlist
-    pub fn load_playlist(&mut self, playlist: &Playlist) {
-        self.current_playlist = Some(playlist);
+    // opens the playlist to show its songs
+    pub fn load_playlist(&mut self, index: usize) {
+        self.current_playlist = Some(index);
     }
 
-    // loads playlist into paybar
-    pub fn load_playbar_playlist(&mut self) {
+    // loads the opened playlist into the playbar and starts it straight away
+    // anything queued by hand still plays once this song is done
+    pub fn load_playbar_playlist(&mut self, library: &Library, playlists: &mut [Playlist]) {
+        self.load_playbar_playlist_from(library, playlists, None);
+    }
+
+    // same as above, but the song picked out of the playlist plays first
+    // shuffle carries on from it the same as when its picked in the playbar
+    pub fn load_playbar_playlist_from(&mut self, library: &Library, playlists: &mut [Playlist], song: Option<usize>) {
+        let index = match self.current_playlist {
+            Some(i) => i,
+            None => return,
+        };
         // don't do anything if the list is empty
-        if self.current_playlist.unwrap().songs.is_empty() {
+        if playlists[index].songs.is_empty() {
             return;
         }
 
-        self.current_songs = Vec::new();
-        self.sink.stop();
-        self.current_playlist.unwrap().prepare_playlist();
-        self.current_songs.append(&mut self.current_playlist.unwrap().songs);
-        self.playbar_source = PlaybarSource::Playlist;
-        self.playing_index = 0;
+        playlists[index].prepare_playlist();
+        self.playing_playlist = Some(index);
+        self.view = QueueView::UpNext;
+        self.hovered_index = 0;
+        let entry = match song {
+            Some(i) => {
+                playlists[index].jump_to(i);
+                playlists[index].choice(i).cloned()
+            }
+            None => playlists[index].get_next_song().map(|(entry, _)| entry),
+        };
+        if let Some(entry) = entry {
+            self.start(library, entry);
+        }
     }
 
-    // removes the playlist from the playbar
+    // the playlist stops feeding the queue, whatever is playing finishes
     pub fn unload_playbar_playlist(&mut self) {
-        self.current_songs = Vec::new();
-        self.playbar_source = PlaybarSource::None;
-        self.sink.stop();
+        self.playing_playlist = None;
+        self.hovered_index = 0;
     }
 
-    // adds regular song
-    pub fn add_normal_song(&mut self, song: &Song) {
-        self.current_songs.push(song);
-        self.playbar_source = PlaybarSource::Normal;
+    // adds a song to the end of the queue
+    pub fn enqueue(&mut self, entry: Entry) {
+        self.queue.push(entry);
+    }
+
+    // adds a song to the front of the queue so its played next
+    pub fn play_next(&mut self, entry: Entry) {
+        self.queue.insert(0, entry);
+    }
+
+    // adds every song of the playlist to the end of the queue
+    pub fn enqueue_playlist(&mut self, playlist: &Playlist) {
+        self.queue.extend(playlist.songs.iter().cloned());
+    }
+
+    // empties up next, the playing song is left to finish
+    pub fn clear_queue(&mut self) {
+        self.queue.clear();
+        self.unload_playbar_playlist();
     }
 
     // function used when enter is pressed on now playing
-    pub fn force_new_song(&mut self) {
-        if self.hovered_index == self.playing_index {
+    pub fn force_new_song(&mut self, library: &Library, playlists: &mut [Playlist]) {
+        let entry = match self.hovered_row(playlists) {
+            Some(QueueRow::Playing) | None => return,
+            Some(QueueRow::Queued(i)) => self.queue.remove(i),
+            // the playlist has to know so shuffle carries on from here
+            Some(QueueRow::Playlist(i)) => {
+                let playlist = match self.playing_playlist.and_then(|p| playlists.get_mut(p)) {
+                    Some(p) => p,
+                    None => return,
+                };
+                playlist.jump_to(i);
+                match playlist.choice(i) {
+                    Some(e) => e.clone(),
+                    None => return,
+                }
+            }
+            Some(QueueRow::History(i)) => self.history.remove(i),
+        };
+        self.start(library, entry);
+        self.clamp_hover(playlists);
+    }
+
+    // a few seconds in it restarts the song, otherwise it goes back to the one before
+    // the song that was playing goes to the front of the queue so its next again
+    pub fn previous(&mut self, library: &Library) {
+        let entry = match self.playing_entry() {
+            Some(e) => e.clone(),
+            None => return,
+        };
+        let heard = self.position().as_secs_f32() / entry.speed();
+        self.record_play(false);
+        let previous = match heard > RESTART_SECONDS {
+            true => None,
+            false => self.history.pop(),
+        };
+        match previous {
+            Some(previous) => {
+                self.queue.insert(0, entry);
+                self.playing = Some(previous.clone());
+                self.play_entry(library, &previous);
+            }
+            // nothing to go back to so the song just starts again
+            None => {
+                self.play_entry(library, &entry);
+            }
+        }
+    }
+
+    // stops the playing song straight away, even when its on repeat
+    pub fn skip(&mut self) {
+        if self.playing_entry().is_none() {
             return;
         }
-        let new_song = &self.current_songs[self.hovered_index].clone();
-        self.playing_index = self.hovered_index;
+        self.skipping = true;
+        self.sink.stop();
+    }
+
+    // 0 to 1, new songs pick it up when theyre added to the sink
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+        self.sink.set_volume(self.volume);
+    }
 
-        self.add_song_to_sink(new_song);
+    pub fn toggle_repeat_one(&mut self) {
+        self.repeat_one = !self.repeat_one;
     }
 
-    // tries loading new song, adds it to sink if successful
-    pub fn try_new_song(&mut self) {
-        if self.current_songs.is_empty() {
-            return;
+    // plays the entry now, the one it replaces goes into the history
+    fn start(&mut self, library: &Library, entry: Entry) {
+        self.record_play(false);
+        if let Some(done) = self.playing.take().filter(|_| self.is_playing) {
+            self.history.push(done);
         }
+        self.skipping = false;
+        self.playing = Some(entry.clone());
+        self.play_entry(library, &entry);
+    }
 
-        let new_song = match self.playbar_source {
-            PlaybarSource::Playlist => {
-                let data = self.current_playlist.clone().unwrap().get_next_song();
-                if data.is_none() { return; }
-                
-                let (song, index) = data.unwrap();
-                self.playing_index = index;
-                song
-            }
-            PlaybarSource::Normal => {
-                if self.is_playing {
-                    self.current_songs.remove(self.playing_index);
-                }
-                if self.current_songs.is_empty() {
-                    self.is_playing = false;
+    // tries loading new song, adds it to sink if successful
+    // the queue comes first, then the loaded playlist
+    pub fn try_new_song(&mut self, library: &Library, playlists: &mut [Playlist]) {
+        let skipping = std::mem::take(&mut self.skipping);
+        // the sink only empties on its own when the song gets to the end
+        self.record_play(!skipping);
+        if self.repeat_one && self.is_playing && !skipping {
+            if let Some(entry) = self.playing.clone() {
+                // songs that cant be played again fall through to the next one
+                if self.play_entry(library, &entry) {
                     return;
                 }
+            }
+        }
 
-                if self.current_songs.len() == self.playing_index {
-                    self.playing_index -= 1;
+        let next = match self.queue.is_empty() {
+            false => Some(self.queue.remove(0)),
+            true => self.playing_playlist
+                .and_then(|i| playlists.get_mut(i))
+                .and_then(|p| p.get_next_song())
+                .map(|(entry, _)| entry),
+        };
+        match next {
+            Some(entry) => self.start(library, entry),
+            None => {
+                if let Some(done) = self.playing.take().filter(|_| self.is_playing) {
+                    self.history.push(done);
                 }
+                self.is_playing = false;
+            }
+        }
+        self.clamp_hover(playlists);
+    }
 
-                self.current_songs[self.playing_index]
+    // plays the entry from the start, songs which went missing in a rescan
+    // count as played so the next call skips them
+    fn play_entry(&mut self, library: &Library, entry: &Entry) -> bool {
+        match library.get(&entry.id).cloned() {
+            Some(song) => {
+                let started = self.add_song_to_sink(&song, entry.speed());
+                if started {
+                    self.listen_start = Some((stats::now(), Duration::ZERO));
+                    self.listened = Duration::ZERO;
+                }
+                started
             }
-            _ => return,
+            None => {
+                self.is_playing = true;
+                false
+            }
+        }
+    }
+
+    // notes down how long the playing song was listened to, once per time its started
+    pub fn record_play(&mut self, completed: bool) {
+        let (started, from) = match self.listen_start.take() {
+            Some(s) => s,
+            None => return,
         };
+        let entry = match &self.playing {
+            Some(e) => e,
+            None => return,
+        };
+        let heard = (self.listened + self.position().saturating_sub(from)).as_secs_f64() / entry.speed() as f64;
+        self.plays.push(Play {
+            id: entry.id.clone(),
+            started,
+            seconds: heard,
+            modifier: entry.tag(),
+            completed,
+        });
+    }
 
-        self.add_song_to_sink(&new_song);
+    // changes the mod of the hovered song, the playing one changes speed straight away
+    pub fn cycle_hovered_mod(&mut self, playlists: &mut [Playlist]) {
+        let row = match self.hovered_row(playlists) {
+            Some(r) => r,
+            None => return,
+        };
+        if let Some(entry) = self.row_entry_mut(row, playlists) {
+            entry.cycle_mod();
+        }
+        self.update_playing_speed(row);
+    }
+
+    // nudges the custom rate of the hovered song
+    pub fn change_hovered_rate(&mut self, playlists: &mut [Playlist], step: f32) {
+        let row = match self.hovered_row(playlists) {
+            Some(r) => r,
+            None => return,
+        };
+        if let Some(entry) = self.row_entry_mut(row, playlists) {
+            entry.change_rate(step);
+        }
+        self.update_playing_speed(row);
+    }
+
+    // puts back the mod and rate the playing song had, nothing happens if another song is on
+    pub fn restore_playing(&mut self, entry: Entry) {
+        if self.playing.as_ref().map(|e| &e.id) != Some(&entry.id) {
+            return;
+        }
+        self.playing = Some(entry);
+        self.update_playing_speed(QueueRow::Playing);
+    }
+
+    // the position is counted before the speed is applied so it stays right
+    fn update_playing_speed(&mut self, row: QueueRow) {
+        if row != QueueRow::Playing {
+            return;
+        }
+        if let Some(speed) = self.playing_entry().map(|e| e.speed()) {
+            self.sink.set_speed(speed);
+        }
+    }
+
+    // puts the song back where it was left last time, paused so it doesnt start blaring on launch
+    pub fn resume(&mut self, library: &Library, entry: Entry, position: Duration) {
+        let song = match library.get(&entry.id) {
+            Some(s) => s.clone(),
+            None => return,
+        };
+        if self.add_song_to_sink_at(&song, entry.speed(), position) {
+            self.sink.pause();
+            self.playing = Some(entry);
+            self.listen_start = Some((stats::now(), position));
+            self.listened = Duration::ZERO;
+        }
+    }
+
+    // jumps to a point in the playing song, it stays paused if it was
+    pub fn seek(&mut self, library: &Library, position: Duration) {
+        let entry = match self.playing_entry() {
+            Some(e) => e.clone(),
+            None => return,
+        };
+        let song = match library.get(&entry.id) {
+            Some(s) => s.clone(),
+            None => return,
+        };
+        let position = position.min(Duration::from_secs(song.length));
+        let now = self.position();
+        if let Some((_, from)) = self.listen_start.as_mut() {
+            self.listened += now.saturating_sub(*from);
+            *from = position;
+        }
+        let paused = self.sink.is_paused();
+        if self.add_song_to_sink_at(&song, entry.speed(), position) && paused {
+            self.sink.pause();
+        }
+    }
+
+    // changes what gets mixed into the song and reloads it where it was
+    pub fn cycle_click_mode(&mut self, library: &Library) {
+        self.click_mode = self.click_mode.next();
+        let entry = match self.playing_entry() {
+            Some(e) => e.clone(),
+            None => return,
+        };
+        if let Some(song) = library.get(&entry.id).cloned() {
+            let position = self.position();
+            let paused = self.sink.is_paused();
+            self.add_song_to_sink_at(&song, entry.speed(), position);
+            if paused {
+                self.sink.pause();
+            }
+        }
     }
 
     // adds a song to the sink
-    fn add_song_to_sink(&mut self, song: &Song) {
-        let path = format!("{}{}", self.parent_path, song.audio_path);
-        let audio_file = File::open(path).unwrap();
-        self.sink = self.stream_handle.play_once(audio_file).unwrap();
-
-        let speed = match song.modifier {
-            Mod::NoMod => 1.0,
-            _ => 1.5,
+    fn add_song_to_sink(&mut self, song: &Song, speed: f32) -> bool {
+        self.add_song_to_sink_at(song, speed, Duration::ZERO)
+    }
+
+    // adds a song to the sink, starting part way through, false if it couldnt be opened
+    fn add_song_to_sink_at(&mut self, song: &Song, speed: f32, start: Duration) -> bool {
+        // files which cant be opened are skipped the same way as missing songs
+        let source = match build_source(song, &self.parent_path, self.click_mode) {
+            Ok(s) => s.skip_duration(start),
+            Err(_) => {
+                self.is_playing = true;
+                return false;
+            }
+        };
+        self.sink = match &self.stream_handle {
+            Some(handle) => Sink::try_new(handle).unwrap(),
+            None => Sink::new_idle().0,
         };
+        // the visualizer reads from whatever passes through the tap
+        self.sink.append(Tap::new(source, self.samples.clone()));
+        if let Ok(mut b) = self.samples.lock() {
+            b.frames_played = (start.as_secs_f64() * b.sample_rate as f64) as u64;
+        }
 
         self.sink.set_speed(speed);
         self.sink.set_volume(self.volume);
 
         self.is_playing = true;
+        true
     }
 }
 
+/// DECODES THE SONG AND MIXES IN THE CLICKS, THE SPEED IS LEFT TO WHATEVER PLAYS IT
+pub fn build_source(song: &Song, parent_path: &str, click_mode: ClickMode) -> Result<Box<dyn Source<Item = f32> + Send>, String> {
+    let path = format!("{}{}", parent_path, song.audio_path);
+    let audio_file = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+    let clicks = ClickTrack::new(song, parent_path, click_mode);
+    let source = Decoder::new(audio_file).map_err(|e| e.to_string())?
+        .convert_samples::<f32>()
+        .mix(clicks);
+    Ok(Box::new(source))
+}
+
+/// playlists store the id and mod of each song
+/// a bare id is from before entries had mods, the full song is the oldest format
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SerializedEntry {
+    Id(String),
+    Entry(Entry),
+    Legacy(LegacySong),
+}
+
+impl SerializedEntry {
+    /// THE SONG IN THE LIBRARY THIS POINTS TO, WITH ITS MOD
+    fn resolve(&self, library: &Library) -> Option<Entry> {
+        let (song, modifier, rate) = match self {
+            SerializedEntry::Id(id) => (library.get(id)?, Mod::NoMod, None),
+            SerializedEntry::Entry(e) => (library.get(&e.id)?, e.modifier, e.rate),
+            // old playlists only have the path to go off
+            SerializedEntry::Legacy(song) => (library.find_by_path(&song.audio_path)?, song.modifier, None),
+        };
+        Some(Entry { id: song.id.clone(), modifier, rate })
+    }
+
+    /// what its called in the list of songs that couldnt be found
+    fn label(&self) -> &str {
+        match self {
+            SerializedEntry::Id(id) => id,
+            SerializedEntry::Entry(e) => &e.id,
+            SerializedEntry::Legacy(song) => &song.song_name,
+        }
+    }
+}
+
+/// the bits of a song from the oldest playlists that are needed to find it again
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LegacySong {
+    audio_path: String,
+    song_name: String,
+    #[serde(default)]
+    modifier: Mod,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SerializedPlaylist {
     name: String,
-    songs: Vec<Song>,
+    songs: Vec<SerializedEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    query: Option<String>,
+}
+
+/// how far a loaded playlist has got, saved with the session so it carries on where it was
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlaybackState {
+    songs: Vec<Entry>,
+    current: Option<usize>,
+    bag: Vec<usize>,
+    cycle_started: bool,
+    removed_played: bool,
+    finished: bool,
+    shuffle_on: bool,
+    repeat_on: bool,
 }
 
 #[derive(Clone, Debug)]
-pub struct Playlist<'a> {
+pub struct Playlist {
     pub name: String,
-    pub songs: Vec<&'a Song>,
+    pub songs: Vec<Entry>,
+    /// smart playlists are a saved search, their songs come from the library
+    pub query: Option<String>,
+    /// songs the library doesnt have, kept as they were so saving doesnt lose them
+    /// each with where it was in the saved list, in order, so it goes back in the same place
+    missing: Vec<(usize, SerializedEntry)>,
     pub shuffle_on: bool,
     pub repeat_on: bool,
-    song_index: usize,
-    song_choice: Vec<&'a Song>,
+    // the songs as they were when loaded into the playbar, everything below indexes into this
+    song_choice: Vec<Entry>,
+    current: Option<usize>,
+    // shuffled songs left to play this cycle, the next one is at the end
+    bag: Vec<usize>,
+    cycle_started: bool,
     removed_played: bool,
+    finished: bool,
+    rng: StdRng,
 }
 
-impl Playlist<'_> {
+impl Playlist {
     pub fn new_empty(name: String) -> Self {
+        Self::new_loaded(name, Vec::new())
+    }
+
+    pub fn new_loaded(name: String, songs: Vec<Entry>) -> Self {
         Self {
             name,
-            songs: Vec::new(),
+            songs,
+            query: None,
+            missing: Vec::new(),
             shuffle_on: false,
             repeat_on: false,
-            song_index: 0,
             song_choice: Vec::new(),
+            current: None,
+            bag: Vec::new(),
+            cycle_started: false,
             removed_played: false,
+            finished: false,
+            rng: StdRng::from_entropy(),
         }
     }
 
-    pub fn new_loaded(serialized: &SerializedPlaylist) -> Self {
-        Self {
-            name: serialized.name.clone(),
-            songs: serialized.songs.iter().collect(),
-            shuffle_on: false,
-            repeat_on: false,
-            song_index: 0,
-            song_choice: Vec::new(),
-            removed_played: false,
+    // the query has to parse, the songs are filled in from the library
+    pub fn new_smart(name: String, query: String, library: &Library, context: &QueryContext) -> Result<Self, String> {
+        Query::parse(&query)?;
+        let mut playlist = Self::new_empty(name);
+        playlist.query = Some(query);
+        playlist.refresh(library, context);
+        Ok(playlist)
+    }
+
+    pub fn is_smart(&self) -> bool {
+        self.query.is_some()
+    }
+
+    // runs the search again so smart playlists pick up library and play count changes
+    pub fn refresh(&mut self, library: &Library, context: &QueryContext) {
+        let query = match self.query.as_ref().map(|q| Query::parse(q)) {
+            Some(Ok(q)) => q,
+            _ => return,
+        };
+        self.songs = library.songs().iter()
+            .filter(|s| query.matches(s, context))
+            .map(|s| Entry::new(s.id.clone(), Mod::NoMod))
+            .collect();
+    }
+
+    // a copy with none of the playing state
+    pub fn duplicate(&self) -> Self {
+        let mut copy = Self::new_loaded(format!("{} (copy)", self.name), self.songs.clone());
+        copy.query = self.query.clone();
+        copy.missing = self.missing.clone();
+        copy
+    }
+
+    /// THE SONGS AS THEYRE SAVED, WITH THE MISSING ONES BACK WHERE THEY WERE
+    fn saved_entries(&self) -> Vec<SerializedEntry> {
+        let mut entries: Vec<SerializedEntry> = self.songs.iter().map(|s| SerializedEntry::Entry(s.clone())).collect();
+        for (position, entry) in &self.missing {
+            entries.insert((*position).min(entries.len()), entry.clone());
         }
+        entries
     }
 
+    /// PUTS BACK THE MISSING SONGS THE LIBRARY HAS AGAIN, LIKE AFTER A RESCAN
+    /// gives back whether any were found
+    pub fn resolve_missing(&mut self, library: &Library) -> bool {
+        let mut still_missing: Vec<(usize, SerializedEntry)> = Vec::new();
+        let mut found_any = false;
+        for (position, entry) in std::mem::take(&mut self.missing) {
+            match entry.resolve(library) {
+                // the songs before it are the found ones and the ones still missing
+                Some(found) => {
+                    let at = position.saturating_sub(still_missing.len()).min(self.songs.len());
+                    self.songs.insert(at, found);
+                    found_any = true;
+                }
+                None => still_missing.push((position, entry)),
+            }
+        }
+        self.missing = still_missing;
+        found_any
+    }
+
+    pub fn move_song(&mut self, from: usize, to: usize) {
+        if from >= self.songs.len() || to >= self.songs.len() {
+            return;
+        }
+        let song = self.songs.remove(from);
+        self.songs.insert(to, song);
+    }
+
+    // starts playing from the top with nothing played yet
     pub fn prepare_playlist(&mut self) {
-        self.song_choice = self.songs;
+        self.song_choice = self.songs.clone();
+        self.current = None;
+        self.bag.clear();
+        self.cycle_started = false;
+        self.removed_played = false;
+        self.finished = false;
     }
 
+    // keeps the indexes pointing at the same songs after one is removed from the playbar
     pub fn remove_playbar_song(&mut self, index: usize) {
         self.song_choice.remove(index);
-        if self.song_index == index {
-            self.removed_played = true;
+        self.bag.retain(|i| *i != index);
+        self.bag.iter_mut().filter(|i| **i > index).for_each(|i| *i -= 1);
+        match self.current {
+            // the song after it has moved into its place, so thats the one played next
+            Some(c) if c == index => self.removed_played = true,
+            Some(c) if c > index => self.current = Some(c - 1),
+            _ => {}
         }
     }
 
-    pub fn get_next_song(&mut self) -> Option<(&Song, usize)> {
+    pub fn get_next_song(&mut self) -> Option<(Entry, usize)> {
+        if self.song_choice.is_empty() || self.finished {
+            return None;
+        }
+        let next = match self.shuffle_on {
+            true => self.next_shuffled(),
+            false => self.next_in_order(),
+        };
+        let index = match next {
+            Some(i) => i,
+            None => {
+                self.finished = true;
+                return None;
+            }
+        };
+        self.set_current(index);
+        Some((self.song_choice[index].clone(), index))
+    }
+
+    pub fn playback_state(&self) -> PlaybackState {
+        PlaybackState {
+            songs: self.song_choice.clone(),
+            current: self.current,
+            bag: self.bag.clone(),
+            cycle_started: self.cycle_started,
+            removed_played: self.removed_played,
+            finished: self.finished,
+            shuffle_on: self.shuffle_on,
+            repeat_on: self.repeat_on,
+        }
+    }
+
+    // indexes past the end are dropped in case the file was edited
+    pub fn restore_playback(&mut self, state: PlaybackState) {
+        let len = state.songs.len();
+        self.song_choice = state.songs;
+        self.current = state.current.filter(|c| *c < len);
+        self.bag = state.bag.into_iter().filter(|i| *i < len).collect();
+        self.cycle_started = state.cycle_started;
+        self.removed_played = state.removed_played;
+        self.finished = state.finished;
+        self.shuffle_on = state.shuffle_on;
+        self.repeat_on = state.repeat_on;
+    }
+
+    pub fn choice(&self, index: usize) -> Option<&Entry> {
+        self.song_choice.get(index)
+    }
+
+    pub fn choice_mut(&mut self, index: usize) -> Option<&mut Entry> {
+        self.song_choice.get_mut(index)
+    }
+
+    // the songs which will play next in order, as far ahead as is already decided
+    pub fn upcoming(&self) -> Vec<usize> {
+        if self.finished {
+            return Vec::new();
+        }
         if self.shuffle_on {
-            let song_index = rand::thread_rng().gen_range(0..self.song_choice.len()-1);
-            let new_song = self.song_choice[song_index];
-            return Some((new_song, song_index));
+            return self.bag.iter().rev().copied().collect();
         }
-        if self.removed_played {
-            self.removed_played = false;
-            return Some((self.song_choice[self.song_index], self.song_index));
+        let start = match self.current {
+            None => 0,
+            Some(c) if self.removed_played => c,
+            Some(c) => c + 1,
+        };
+        (start..self.song_choice.len()).collect()
+    }
+
+    // plays the song picked in the playbar, it counts as played for the shuffle
+    pub fn jump_to(&mut self, index: usize) {
+        if index >= self.song_choice.len() {
+            return;
         }
-        self.song_index += 1;
-        if self.song_index < self.song_choice.len() {
-            return Some((self.song_choice[self.song_index-1], self.song_index-1));
+        self.bag.retain(|i| *i != index);
+        self.finished = false;
+        self.set_current(index);
+    }
+
+    fn set_current(&mut self, index: usize) {
+        self.removed_played = false;
+        self.current = Some(index);
+    }
+
+    fn next_in_order(&self) -> Option<usize> {
+        let next = match self.current {
+            None => 0,
+            Some(c) if self.removed_played => c,
+            Some(c) => c + 1,
+        };
+        match next < self.song_choice.len() {
+            true => Some(next),
+            false if self.repeat_on => Some(0),
+            false => None,
         }
+    }
+
+    // every song is played once before any of them play again
+    fn next_shuffled(&mut self) -> Option<usize> {
+        if self.bag.is_empty() {
+            if self.cycle_started && !self.repeat_on {
+                return None;
+            }
+            self.refill_bag();
+        }
+        self.bag.pop()
+    }
 
-        self.song_index = 0;
-        if self.repeat_on {
-            return Some((self.song_choice[self.song_index], self.song_index));
+    fn refill_bag(&mut self) {
+        self.bag = (0..self.song_choice.len()).collect();
+        self.bag.shuffle(&mut self.rng);
+        // the new cycle cant start with the song that just finished the last one
+        if self.bag.len() > 1 && self.current.is_some() && self.bag.last() == self.current.as_ref() {
+            let last = self.bag.len() - 1;
+            self.bag.swap(0, last);
         }
-        return None;
+        self.cycle_started = true;
     }
 
+    // turning shuffle on starts a fresh cycle
     pub fn toggle_shuffle(&mut self) {
         self.shuffle_on = !self.shuffle_on;
+        self.bag.clear();
+        self.cycle_started = false;
     }
     
     pub fn toggle_repeat(&mut self) {
@@ -264,25 +936,248 @@ impl Playlist<'_> {
 
 }
 
-pub fn serialize_playlists(playlists: &Vec<Playlist>, path: &str) {
+pub fn serialize_playlists(playlists: &[Playlist], path: &str) -> io::Result<()> {
     let raw_data: Vec<SerializedPlaylist> = playlists.iter().map(|p| {
+        // smart playlists are rebuilt on load so their songs arent worth saving
+        let songs = match p.is_smart() {
+            true => Vec::new(),
+            false => p.saved_entries(),
+        };
         SerializedPlaylist {
-            name: p.name,
-            songs: p.songs.iter().map(|s| **s).collect()
+            name: p.name.clone(),
+            songs,
+            query: p.query.clone(),
         }
     }).collect();
 
-    serialize::serialize(&raw_data, path);
+    serialize::serialize(&raw_data, path)
 }
 
-pub fn deserialize_playlist(path: &str) -> Option<Vec<Playlist>> {
-    let raw_data: Result<Vec<SerializedPlaylist>, _> = deserialize(path);
-    if raw_data.is_err() {
-        return None;
-    }
-    let playlist_data = raw_data.unwrap();
-    let playlists = playlist_data.iter().map(|p| {
-        Playlist::new_loaded(p)
+/// LOADS THE PLAYLISTS AND MATCHES THEIR SONGS TO THE LIBRARY
+/// songs which cant be found are returned as well, and kept aside so theyre saved again
+/// a missing file is no playlists yet, any other error is passed on so the file isnt saved over
+pub fn deserialize_playlist(path: &str, library: &Library) -> io::Result<(Vec<Playlist>, Vec<String>)> {
+    let playlist_data: Vec<SerializedPlaylist> = match deserialize(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((Vec::new(), Vec::new())),
+        result => result?,
+    };
+
+    let mut unresolved = Vec::new();
+    let mut migrated = false;
+
+    let playlists: Vec<Playlist> = playlist_data.iter().map(|p| {
+        let mut songs = Vec::new();
+        let mut missing = Vec::new();
+        for (position, entry) in p.songs.iter().enumerate() {
+            match entry.resolve(library) {
+                Some(found) => {
+                    migrated |= matches!(entry, SerializedEntry::Legacy(_));
+                    songs.push(found);
+                }
+                None => {
+                    unresolved.push(format!("{} ({})", entry.label(), p.name));
+                    missing.push((position, entry.clone()));
+                }
+            }
+        }
+        // smart playlists get their songs once the rest of the app is loaded
+        let mut playlist = Playlist::new_loaded(p.name.clone(), songs);
+        playlist.query = p.query.clone();
+        playlist.missing = missing;
+        playlist
     }).collect();
-    Some(playlists)
+
+    // rewrite old playlists with ids, keeping the original just in case
+    if migrated {
+        let _ = fs::copy(path, format!("{}.bak", path));
+        let _ = serialize_playlists(&playlists, path);
+    }
+    Ok((playlists, unresolved))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seeded_playlist(len: usize, seed: u64) -> Playlist {
+        let songs = (0..len).map(|i| Entry::new(i.to_string(), Mod::NoMod)).collect();
+        let mut playlist = Playlist::new_loaded(String::from("test"), songs);
+        playlist.rng = StdRng::seed_from_u64(seed);
+        playlist.shuffle_on = true;
+        playlist.prepare_playlist();
+        playlist
+    }
+
+    fn next_index(playlist: &mut Playlist) -> Option<usize> {
+        playlist.get_next_song().map(|(_, i)| i)
+    }
+
+    #[test]
+    fn shuffle_plays_every_song_once_per_cycle() {
+        let mut playlist = seeded_playlist(10, 1);
+        playlist.repeat_on = true;
+        for _ in 0..5 {
+            let mut cycle: Vec<usize> = (0..10).map(|_| next_index(&mut playlist).unwrap()).collect();
+            cycle.sort();
+            assert_eq!(cycle, (0..10).collect::<Vec<usize>>());
+        }
+    }
+
+    #[test]
+    fn shuffle_never_repeats_back_to_back() {
+        for seed in 0..50 {
+            let mut playlist = seeded_playlist(3, seed);
+            playlist.repeat_on = true;
+            let mut last = next_index(&mut playlist);
+            for _ in 0..30 {
+                let next = next_index(&mut playlist);
+                assert_ne!(next, last, "seed {}", seed);
+                last = next;
+            }
+        }
+    }
+
+    #[test]
+    fn shuffle_stops_after_one_cycle_without_repeat() {
+        let mut playlist = seeded_playlist(4, 2);
+        for _ in 0..4 {
+            assert!(next_index(&mut playlist).is_some());
+        }
+        assert_eq!(next_index(&mut playlist), None);
+        assert_eq!(next_index(&mut playlist), None);
+    }
+
+    #[test]
+    fn single_song_playlist_does_not_panic() {
+        let mut playlist = seeded_playlist(1, 3);
+        assert_eq!(next_index(&mut playlist), Some(0));
+        assert_eq!(next_index(&mut playlist), None);
+
+        let mut playlist = seeded_playlist(1, 3);
+        playlist.repeat_on = true;
+        for _ in 0..3 {
+            assert_eq!(next_index(&mut playlist), Some(0));
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_order() {
+        let mut first = seeded_playlist(20, 42);
+        let mut second = seeded_playlist(20, 42);
+        for _ in 0..20 {
+            assert_eq!(next_index(&mut first), next_index(&mut second));
+        }
+    }
+
+    #[test]
+    fn upcoming_matches_what_plays_next() {
+        let mut playlist = seeded_playlist(6, 4);
+        next_index(&mut playlist);
+        let upcoming = playlist.upcoming();
+        assert_eq!(upcoming.len(), 5);
+        for expected in upcoming {
+            assert_eq!(next_index(&mut playlist), Some(expected));
+        }
+    }
+
+    #[test]
+    fn jumping_to_a_song_takes_it_out_of_the_cycle() {
+        let mut playlist = seeded_playlist(5, 7);
+        next_index(&mut playlist);
+        let picked = playlist.upcoming()[2];
+        playlist.jump_to(picked);
+        assert!(!playlist.upcoming().contains(&picked));
+        assert_eq!(playlist.upcoming().len(), 3);
+    }
+
+    #[test]
+    fn in_order_wraps_only_with_repeat() {
+        let mut playlist = seeded_playlist(3, 5);
+        playlist.shuffle_on = false;
+        let played: Vec<Option<usize>> = (0..4).map(|_| next_index(&mut playlist)).collect();
+        assert_eq!(played, vec![Some(0), Some(1), Some(2), None]);
+
+        playlist.prepare_playlist();
+        playlist.repeat_on = true;
+        let played: Vec<Option<usize>> = (0..4).map(|_| next_index(&mut playlist)).collect();
+        assert_eq!(played, vec![Some(0), Some(1), Some(2), Some(0)]);
+    }
+
+    #[test]
+    fn removing_the_playing_song_plays_the_one_after() {
+        let mut playlist = seeded_playlist(4, 6);
+        playlist.shuffle_on = false;
+        next_index(&mut playlist);
+        next_index(&mut playlist);
+        playlist.remove_playbar_song(1);
+        assert_eq!(playlist.get_next_song().map(|(e, _)| e.id), Some(String::from("2")));
+    }
+
+    fn test_song(id: &str, audio_path: &str) -> Song {
+        Song {
+            id: id.to_string(),
+            audio_path: audio_path.to_string(),
+            song_name: String::new(),
+            artist: String::new(),
+            length: 0,
+            beatmap_path: String::new(),
+            timing_points: Vec::new(),
+        }
+    }
+
+    fn labels(playlist: &Playlist) -> Vec<String> {
+        playlist.saved_entries().iter().map(|e| e.label().to_string()).collect()
+    }
+
+    #[test]
+    fn songs_missing_from_the_library_keep_their_place() {
+        let library = Library::new(vec![test_song("a", "a/audio.mp3"), test_song("c", "c/audio.mp3")]);
+        let path = std::env::temp_dir().join(format!("sposu-playlists-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, r#"[{"name": "gym", "songs": [
+            "gone",
+            {"id": "a", "modifier": "NoMod"},
+            {"audio_path": "b/audio.mp3", "song_name": "b"},
+            "c"
+        ]}]"#).unwrap();
+
+        let (playlists, unresolved) = deserialize_playlist(path, &library).unwrap();
+        assert_eq!(unresolved.len(), 2);
+        assert_eq!(playlists[0].songs, vec![Entry::new(String::from("a"), Mod::NoMod), Entry::new(String::from("c"), Mod::NoMod)]);
+
+        serialize_playlists(&playlists, path).unwrap();
+        let (playlists, unresolved) = deserialize_playlist(path, &library).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(unresolved.len(), 2);
+        assert!(matches!(playlists[0].missing[..], [(0, SerializedEntry::Id(_)), (2, SerializedEntry::Legacy(_))]));
+        assert_eq!(labels(&playlists[0]), vec!["gone", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn songs_found_again_go_back_in_place() {
+        let mut playlist = Playlist::new_loaded(String::from("gym"), vec![Entry::new(String::from("a"), Mod::NoMod), Entry::new(String::from("c"), Mod::NoMod)]);
+        playlist.missing = vec![
+            (0, SerializedEntry::Id(String::from("gone"))),
+            (2, SerializedEntry::Id(String::from("b"))),
+        ];
+        assert!(!playlist.resolve_missing(&Library::new(vec![test_song("a", "a"), test_song("c", "c")])));
+
+        let library = Library::new(vec![test_song("a", "a"), test_song("b", "b"), test_song("c", "c")]);
+        assert!(playlist.resolve_missing(&library));
+        let ids: Vec<&str> = playlist.songs.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+        assert_eq!(labels(&playlist), vec!["gone", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn loading_from_a_song_plays_it_first() {
+        let mut player = Player::silent();
+        let mut playlists = vec![seeded_playlist(4, 7)];
+        playlists[0].shuffle_on = false;
+        player.current_playlist = Some(0);
+        player.load_playbar_playlist_from(&Library::new(Vec::new()), &mut playlists, Some(2));
+        assert_eq!(player.playing_playlist, Some(0));
+        assert_eq!(player.playing.map(|e| e.id), Some(String::from("2")));
+        assert_eq!(next_index(&mut playlists[0]), Some(3));
+    }
 }
@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::export::sanitize_file_name;
+use crate::library::Library;
+use crate::osu::{Song, Entry, Mod};
+use crate::player::Playlist;
+
+/// playlist formats other players understand
+#[derive(Copy, Clone, Debug)]
+pub enum PlaylistFormat {
+    M3u,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            PlaylistFormat::M3u => "m3u8",
+            PlaylistFormat::Xspf => "xspf",
+        }
+    }
+
+    /// the format as its written on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "m3u" | "m3u8" => Some(PlaylistFormat::M3u),
+            "xspf" => Some(PlaylistFormat::Xspf),
+            _ => None,
+        }
+    }
+
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_string_lossy().to_lowercase().as_str() {
+            "m3u" | "m3u8" => Some(PlaylistFormat::M3u),
+            "xspf" => Some(PlaylistFormat::Xspf),
+            _ => None,
+        }
+    }
+}
+
+/// WHERE THE SONGS AUDIO IS ON DISK, MADE ABSOLUTE SO OTHER PROGRAMS CAN FIND IT
+fn absolute_audio_path(parent_path: &str, song: &Song) -> PathBuf {
+    let path = PathBuf::from(format!("{}{}", parent_path, song.audio_path));
+    if path.is_absolute() {
+        return path;
+    }
+    env::current_dir().map(|d| d.join(&path)).unwrap_or(path)
+}
+
+/// paths are compared with forward slashes, and ignoring case on windows where the files do too
+/// files that exist are canonicalized so links and ".." match, the rest have ".." resolved by hand
+fn normalize_path(path: &Path) -> String {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let path = path.to_string_lossy().replace('\\', "/");
+    let path = path.strip_prefix("//?/").unwrap_or(&path);
+    let mut parts: Vec<&str> = Vec::new();
+    for (i, part) in path.split('/').enumerate() {
+        match part {
+            // the empty first part is the root of an absolute path
+            "" if i > 0 => {}
+            "." => {}
+            ".." => match parts.last() {
+                Some(&"") if parts.len() == 1 => {}
+                Some(last) if *last != ".." => { parts.pop(); }
+                _ => parts.push(part),
+            },
+            _ => parts.push(part),
+        }
+    }
+    let path = parts.join("/");
+    match cfg!(windows) {
+        true => path.to_lowercase(),
+        false => path,
+    }
+}
+
+/// "C:" or "http://" at the start, which path.is_absolute() misses for windows paths on other systems
+fn has_drive_or_scheme(path: &str) -> bool {
+    if let [drive, b':', ..] = path.as_bytes() {
+        return drive.is_ascii_alphabetic();
+    }
+    match path.split_once("://") {
+        Some((scheme, _)) => scheme.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)),
+        None => false,
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// TURNS A PATH INTO A file:// URI, ESCAPING EVERYTHING THAT ISNT SAFE
+fn path_to_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    // windows paths need the extra slash before the drive letter
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' | b':' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+fn uri_to_path(uri: &str) -> PathBuf {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    let decoded = String::from_utf8_lossy(&decoded).to_string();
+    // "/C:/..." is really "C:/..."
+    match decoded.as_bytes() {
+        [b'/', _, b':', ..] => PathBuf::from(&decoded[1..]),
+        _ => PathBuf::from(decoded),
+    }
+}
+
+fn to_m3u(playlist: &Playlist, library: &Library, parent_path: &str) -> String {
+    let mut content = String::from("#EXTM3U\n");
+    content.push_str(&format!("#PLAYLIST:{}\n", playlist.name));
+    for song in playlist.songs.iter().filter_map(|e| library.get(&e.id)) {
+        content.push_str(&format!("#EXTINF:{},{} - {}\n", song.length, song.artist, song.song_name));
+        content.push_str(&format!("{}\n", absolute_audio_path(parent_path, song).to_string_lossy()));
+    }
+    content
+}
+
+fn to_xspf(playlist: &Playlist, library: &Library, parent_path: &str) -> String {
+    let mut content = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    content.push_str("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n");
+    content.push_str(&format!("  <title>{}</title>\n", xml_escape(&playlist.name)));
+    content.push_str("  <trackList>\n");
+    for song in playlist.songs.iter().filter_map(|e| library.get(&e.id)) {
+        content.push_str("    <track>\n");
+        content.push_str(&format!("      <location>{}</location>\n", xml_escape(&path_to_uri(&absolute_audio_path(parent_path, song)))));
+        content.push_str(&format!("      <title>{}</title>\n", xml_escape(&song.song_name)));
+        content.push_str(&format!("      <creator>{}</creator>\n", xml_escape(&song.artist)));
+        content.push_str(&format!("      <duration>{}</duration>\n", song.length * 1000));
+        content.push_str("    </track>\n");
+    }
+    content.push_str("  </trackList>\n</playlist>\n");
+    content
+}
+
+/// WRITES THE PLAYLIST TO THE DIRECTORY IN THE FORMAT, NAMED AFTER THE PLAYLIST
+pub fn export_playlist(playlist: &Playlist, library: &Library, parent_path: &str, format: PlaylistFormat, directory: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(directory)?;
+    let name = format!("{}.{}", sanitize_file_name(&playlist.name), format.extension());
+    let path = Path::new(directory).join(name);
+    write_playlist(playlist, library, parent_path, format, &path)?;
+    Ok(path)
+}
+
+/// WRITES THE PLAYLIST TO THE EXACT PATH IN THE FORMAT, WHATEVER THE EXTENSION SAYS
+pub fn write_playlist(playlist: &Playlist, library: &Library, parent_path: &str, format: PlaylistFormat, path: &Path) -> io::Result<()> {
+    let content = match format {
+        PlaylistFormat::Xspf => to_xspf(playlist, library, parent_path),
+        PlaylistFormat::M3u => to_m3u(playlist, library, parent_path),
+    };
+    fs::write(path, content)
+}
+
+/// all the tracks in the file, relative ones are taken from where the playlist is
+fn read_locations(content: &str, format: PlaylistFormat, directory: &Path) -> Vec<PathBuf> {
+    let locations: Vec<PathBuf> = match format {
+        PlaylistFormat::M3u => content.lines()
+            .map(|l| l.trim().trim_start_matches('\u{feff}'))
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            // plain paths arent escaped so only uris get decoded
+            .map(|l| match l.starts_with("file://") {
+                true => uri_to_path(l),
+                false => PathBuf::from(l),
+            })
+            .collect(),
+        PlaylistFormat::Xspf => content.split("<location>")
+            .skip(1)
+            .filter_map(|part| part.split("</location>").next())
+            .map(|l| uri_to_path(&xml_unescape(l.trim())))
+            .collect(),
+    };
+    locations.into_iter().map(|l| match l.is_absolute() || has_drive_or_scheme(&l.to_string_lossy()) {
+        true => l,
+        false => directory.join(l),
+    }).collect()
+}
+
+/// READS AN M3U OR XSPF FILE AND MATCHES ITS PATHS TO SONGS IN THE LIBRARY
+/// the tracks which arent in the library are returned alongside the playlist
+pub fn import_playlist(path: &str, library: &Library, parent_path: &str) -> Result<(Playlist, Vec<String>), String> {
+    let path = Path::new(path);
+    let format = PlaylistFormat::from_path(path).ok_or("only .m3u, .m3u8 and .xspf can be imported")?;
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let directory = path.parent().unwrap_or(Path::new("."));
+    let directory = env::current_dir().map(|d| d.join(directory)).unwrap_or(directory.to_path_buf());
+
+    let by_path: HashMap<String, &Song> = library.songs().iter().map(|s| {
+        (normalize_path(&absolute_audio_path(parent_path, s)), s)
+    }).collect();
+
+    let mut songs = Vec::new();
+    let mut unmatched = Vec::new();
+    for location in read_locations(&content, format, &directory) {
+        match by_path.get(&normalize_path(&location)) {
+            Some(song) => songs.push(Entry::new(song.id.clone(), Mod::NoMod)),
+            None => unmatched.push(location.to_string_lossy().to_string()),
+        }
+    }
+
+    let name = path.file_stem().map_or(String::from("imported"), |s| s.to_string_lossy().to_string());
+    Ok((Playlist::new_loaded(name, songs), unmatched))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 5] = [
+        "/songs/123 Artist - Title/audio.mp3",
+        "/songs/100% & more/a+b=c.ogg",
+        "/songs/%20 isnt a space/audio.mp3",
+        "/songs/ナイト・オブ・ナイツ/ÄÖÜ é.mp3",
+        "/songs/Artist: Title [Hard]/audio #1.mp3",
+    ];
+
+    #[test]
+    fn uris_round_trip() {
+        for name in NAMES {
+            let uri = path_to_uri(Path::new(name));
+            assert!(uri.starts_with("file:///"), "{}", uri);
+            assert!(!uri[7..].contains([' ', '&', '#']), "{}", uri);
+            assert_eq!(uri_to_path(&uri), PathBuf::from(name));
+        }
+    }
+
+    #[test]
+    fn uris_are_escaped() {
+        assert_eq!(path_to_uri(Path::new("/a b/100%.mp3")), "file:///a%20b/100%25.mp3");
+        assert_eq!(path_to_uri(Path::new("/é")), "file:///%C3%A9");
+        assert_eq!(uri_to_path("file:///C:/a%20b"), PathBuf::from("C:/a b"));
+        // a stray % is left alone
+        assert_eq!(uri_to_path("file:///a%zz%"), PathBuf::from("/a%zz%"));
+    }
+
+    #[test]
+    fn xspf_locations_round_trip() {
+        let content: String = NAMES.iter()
+            .map(|n| format!("<track><location>{}</location></track>\n", xml_escape(&path_to_uri(Path::new(n)))))
+            .collect();
+        let locations = read_locations(&content, PlaylistFormat::Xspf, Path::new("/playlists"));
+        assert_eq!(locations, NAMES.map(PathBuf::from));
+    }
+
+    #[test]
+    fn xspf_locations_are_unescaped() {
+        let content = "<location>\n  file:///a%20&amp;%20b/c&amp;amp;d.mp3\n</location>";
+        let locations = read_locations(content, PlaylistFormat::Xspf, Path::new("/playlists"));
+        assert_eq!(locations, vec![PathBuf::from("/a & b/c&amp;d.mp3")]);
+    }
+
+    #[test]
+    fn m3u_relative_paths_are_from_the_playlist() {
+        let content = "\u{feff}#EXTM3U\n#EXTINF:1,x\nArtist: Title.mp3\n\nsub/a b.mp3\n/abs/c.mp3\nC:\\music\\d.mp3\nfile:///e%20f.mp3\n";
+        let locations = read_locations(content, PlaylistFormat::M3u, Path::new("/playlists"));
+        assert_eq!(locations, vec![
+            PathBuf::from("/playlists/Artist: Title.mp3"),
+            PathBuf::from("/playlists/sub/a b.mp3"),
+            PathBuf::from("/abs/c.mp3"),
+            PathBuf::from("C:\\music\\d.mp3"),
+            PathBuf::from("/e f.mp3"),
+        ]);
+    }
+
+    #[test]
+    fn drives_and_schemes_are_absolute() {
+        assert!(has_drive_or_scheme("C:\\music"));
+        assert!(has_drive_or_scheme("d:/music"));
+        assert!(has_drive_or_scheme("http://example.com/a.mp3"));
+        assert!(!has_drive_or_scheme("Artist: Title.mp3"));
+        assert!(!has_drive_or_scheme("a b://c.mp3"));
+        assert!(!has_drive_or_scheme("1:2.mp3"));
+        assert!(!has_drive_or_scheme("audio.mp3"));
+    }
+
+    #[test]
+    fn paths_only_ignore_case_on_windows() {
+        let path = normalize_path(Path::new("Songs\\./Audio.MP3"));
+        match cfg!(windows) {
+            true => assert_eq!(path, "songs/audio.mp3"),
+            false => assert_eq!(path, "Songs/Audio.MP3"),
+        }
+    }
+
+    #[test]
+    fn dot_dots_are_resolved() {
+        assert_eq!(normalize_path(Path::new("/missing/playlists/../songs/./a.mp3")), "/missing/songs/a.mp3");
+        assert_eq!(normalize_path(Path::new("/../a//b/../c.mp3")), "/a/c.mp3");
+        assert_eq!(normalize_path(Path::new("../a.mp3")), "../a.mp3");
+    }
+
+    #[test]
+    fn relative_entries_match_the_library() {
+        let root = env::temp_dir().join(format!("sposu_import_{}", std::process::id()));
+        fs::create_dir_all(root.join("songs/1 a")).unwrap();
+        fs::create_dir_all(root.join("playlists")).unwrap();
+        fs::write(root.join("songs/1 a/audio.mp3"), "").unwrap();
+        fs::write(root.join("playlists/list.m3u"), "../songs/1 a/audio.mp3\n../songs/2 b/audio.mp3\n").unwrap();
+        let song = Song {
+            id: String::from("1"),
+            audio_path: String::from("1 a/audio.mp3"),
+            song_name: String::new(),
+            artist: String::new(),
+            length: 0,
+            beatmap_path: String::new(),
+            timing_points: Vec::new(),
+        };
+        let library = Library::new(vec![song]);
+        let parent_path = format!("{}/", root.join("songs").to_string_lossy());
+        let imported = import_playlist(&root.join("playlists/list.m3u").to_string_lossy(), &library, &parent_path);
+        fs::remove_dir_all(&root).unwrap();
+
+        let (playlist, unmatched) = imported.unwrap();
+        assert_eq!(playlist.songs.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["1"]);
+        assert_eq!(unmatched.len(), 1);
+        assert!(unmatched[0].ends_with("2 b/audio.mp3"), "{}", unmatched[0]);
+    }
+}
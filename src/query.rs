@@ -0,0 +1,344 @@
+use std::cmp::Ordering;
+
+use crate::library::Library;
+use crate::osu::Song;
+use crate::stats::{self, Stats};
+use crate::user_data::UserData;
+
+/// what a query can know about a song besides the song itself
+pub struct QueryContext<'a> {
+    pub stats: &'a Stats,
+    pub user_data: &'a UserData,
+    pub now: u64,
+}
+
+impl<'a> QueryContext<'a> {
+    pub fn new(stats: &'a Stats, user_data: &'a UserData) -> Self {
+        Self { stats, user_data, now: stats::now() }
+    }
+
+    fn play_count(&self, song: &Song) -> u32 {
+        self.stats.song(&song.id).map_or(0, |s| s.play_count)
+    }
+
+    /// never played songs are treated as played forever ago
+    fn days_since_played(&self, song: &Song) -> f64 {
+        match self.stats.song(&song.id) {
+            Some(s) if s.last_played > 0 => self.now.saturating_sub(s.last_played) as f64 / stats::DAY as f64,
+            _ => f64::INFINITY,
+        }
+    }
+}
+
+/// how a number in the query is compared against the song
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Comparison {
+    Less,
+    LessEqual,
+    Equal,
+    GreaterEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn check(&self, value: f64, target: f64) -> bool {
+        match self {
+            Comparison::Less => value < target,
+            Comparison::LessEqual => value <= target,
+            // bpms are rarely whole numbers so equal is a bit loose
+            Comparison::Equal => (value - target).abs() < 0.5,
+            Comparison::GreaterEqual => value >= target,
+            Comparison::Greater => value > target,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Term {
+    /// plain words match the title
+    Text(String),
+    Artist(String),
+    Title(String),
+    Bpm(Comparison, f64),
+    /// in seconds
+    Length(Comparison, f64),
+    Plays(Comparison, f64),
+    /// days since it was last played
+    LastPlayed(Comparison, f64),
+    /// unrated songs count as 0 stars
+    Rating(Comparison, f64),
+    Favourite(bool),
+    /// has to be one of the songs tags exactly
+    Tag(String),
+    Note(String),
+}
+
+/// A PARSED SEARCH LIKE `artist:"xi" bpm>170 length<3:00`, EVERY TERM HAS TO MATCH
+#[derive(Clone, Debug, Default)]
+pub struct Query {
+    terms: Vec<Term>,
+}
+
+impl Query {
+    /// a query which only matches the text against titles, used while the real one is half typed
+    pub fn text(text: &str) -> Self {
+        let terms = split_words(text).into_iter()
+            .map(|w| Term::Text(w.to_lowercase()))
+            .collect();
+        Self { terms }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let terms = split_words(text).iter()
+            .map(|w| parse_term(w))
+            .collect::<Result<Vec<Term>, String>>()?;
+        Ok(Self { terms })
+    }
+
+    pub fn matches(&self, song: &Song, context: &QueryContext) -> bool {
+        self.terms.iter().all(|term| match term {
+            Term::Text(text) | Term::Title(text) => song.song_name.to_lowercase().contains(text),
+            Term::Artist(text) => song.artist.to_lowercase().contains(text),
+            Term::Bpm(comparison, target) => song.main_bpm().is_some_and(|b| comparison.check(b, *target)),
+            Term::Length(comparison, target) => comparison.check(song.length as f64, *target),
+            Term::Plays(comparison, target) => comparison.check(context.play_count(song) as f64, *target),
+            Term::LastPlayed(comparison, target) => comparison.check(context.days_since_played(song), *target),
+            Term::Rating(comparison, target) => comparison.check(context.user_data.rating(&song.id) as f64, *target),
+            Term::Favourite(wanted) => context.user_data.is_favourite(&song.id) == *wanted,
+            Term::Tag(tag) => context.user_data.tags(&song.id).contains(tag),
+            Term::Note(text) => context.user_data.note(&song.id).to_lowercase().contains(text),
+        })
+    }
+}
+
+/// SPLITS ON SPACES EXCEPT INSIDE QUOTES, THE QUOTES THEMSELVES ARE DROPPED
+pub fn split_words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ' ' if !quoted => {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn parse_term(word: &str) -> Result<Term, String> {
+    let split = word.find([':', '<', '>', '=']);
+    let (field, rest) = match split {
+        Some(i) => (word[..i].to_lowercase(), &word[i..]),
+        None => return Ok(Term::Text(word.to_lowercase())),
+    };
+
+    let (comparison, value) = match rest {
+        r if r.starts_with("<=") => (Comparison::LessEqual, &r[2..]),
+        r if r.starts_with(">=") => (Comparison::GreaterEqual, &r[2..]),
+        r if r.starts_with('<') => (Comparison::Less, &r[1..]),
+        r if r.starts_with('>') => (Comparison::Greater, &r[1..]),
+        r => (Comparison::Equal, &r[1..]),
+    };
+    let text_only = |term: fn(String) -> Term| match comparison {
+        Comparison::Equal => Ok(term(value.to_lowercase())),
+        _ => Err(format!("{} can only be matched with ':'", field)),
+    };
+
+    match field.as_str() {
+        "artist" => text_only(Term::Artist),
+        "title" => text_only(Term::Title),
+        "tag" => text_only(Term::Tag),
+        "note" => text_only(Term::Note),
+        "bpm" => Ok(Term::Bpm(comparison, parse_number(value)?)),
+        "length" => Ok(Term::Length(comparison, parse_length(value)?)),
+        "plays" => Ok(Term::Plays(comparison, parse_number(value)?)),
+        "lastplayed" => Ok(Term::LastPlayed(comparison, parse_number(value)?)),
+        "rating" => Ok(Term::Rating(comparison, parse_number(value)?)),
+        "fav" if comparison == Comparison::Equal => Ok(Term::Favourite(parse_yes_no(value)?)),
+        "fav" => Err(String::from("fav can only be matched with ':'")),
+        _ => Err(format!("unknown field '{}'", field)),
+    }
+}
+
+fn parse_number(value: &str) -> Result<f64, String> {
+    value.parse().map_err(|_| format!("'{}' isn't a number", value))
+}
+
+fn parse_yes_no(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "yes" | "y" | "true" => Ok(true),
+        "no" | "n" | "false" => Ok(false),
+        _ => Err(format!("'{}' should be yes or no", value)),
+    }
+}
+
+/// LENGTHS CAN BE GIVEN AS SECONDS OR AS MINUTES:SECONDS
+fn parse_length(value: &str) -> Result<f64, String> {
+    match value.split_once(':') {
+        Some((minutes, seconds)) => Ok(parse_number(minutes)? * 60.0 + parse_number(seconds)?),
+        None => parse_number(value),
+    }
+}
+
+/// what the search results are ordered by
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SortKey {
+    /// the order the songs were scanned in
+    Library,
+    Title,
+    Artist,
+    Bpm,
+    Length,
+    Plays,
+    LastPlayed,
+    Rating,
+}
+
+impl SortKey {
+    const ALL: [SortKey; 8] = [
+        SortKey::Library,
+        SortKey::Title,
+        SortKey::Artist,
+        SortKey::Bpm,
+        SortKey::Length,
+        SortKey::Plays,
+        SortKey::LastPlayed,
+        SortKey::Rating,
+    ];
+
+    pub fn next(&self) -> Self {
+        let i = Self::ALL.iter().position(|k| k == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortKey::Library => "none",
+            SortKey::Title => "title",
+            SortKey::Artist => "artist",
+            SortKey::Bpm => "bpm",
+            SortKey::Length => "length",
+            SortKey::Plays => "plays",
+            SortKey::LastPlayed => "lastplayed",
+            SortKey::Rating => "rating",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.name() == name)
+    }
+
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        Self::ALL.into_iter().map(|k| k.name())
+    }
+}
+
+/// SORTS INDEXES INTO THE LIBRARY, MOST PLAYED, MOST RECENT AND HIGHEST RATED COME FIRST
+pub fn sort_songs(indexes: &mut [usize], key: SortKey, library: &Library, context: &QueryContext) {
+    let songs = library.songs();
+    let text = |a: &str, b: &str| a.to_lowercase().cmp(&b.to_lowercase());
+    let number = |a: f64, b: f64| a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+    indexes.sort_by(|a, b| {
+        let (a, b) = (&songs[*a], &songs[*b]);
+        match key {
+            SortKey::Library => Ordering::Equal,
+            SortKey::Title => text(&a.song_name, &b.song_name),
+            SortKey::Artist => text(&a.artist, &b.artist).then_with(|| text(&a.song_name, &b.song_name)),
+            SortKey::Bpm => number(a.main_bpm().unwrap_or(0.0), b.main_bpm().unwrap_or(0.0)),
+            SortKey::Length => a.length.cmp(&b.length),
+            SortKey::Plays => context.play_count(b).cmp(&context.play_count(a)),
+            SortKey::LastPlayed => number(context.days_since_played(a), context.days_since_played(b)),
+            SortKey::Rating => context.user_data.rating(&b.id).cmp(&context.user_data.rating(&a.id)),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_split_on_spaces_outside_quotes() {
+        assert_eq!(split_words("  a  b "), vec!["a", "b"]);
+        assert_eq!(split_words(r#"artist:"camellia xi" bpm>170"#), vec!["artist:camellia xi", "bpm>170"]);
+        assert_eq!(split_words(r#""" a"#), vec!["a"]);
+        // an unclosed quote runs to the end
+        assert_eq!(split_words(r#"title:"half typed"#), vec!["title:half typed"]);
+    }
+
+    #[test]
+    fn plain_words_match_titles() {
+        assert_eq!(parse_term("Freedom").unwrap(), Term::Text(String::from("freedom")));
+        assert_eq!(parse_term("ARTIST:Xi").unwrap(), Term::Artist(String::from("xi")));
+        assert_eq!(parse_term("title:a:b").unwrap(), Term::Title(String::from("a:b")));
+    }
+
+    #[test]
+    fn comparisons_are_read() {
+        assert_eq!(parse_term("bpm<170").unwrap(), Term::Bpm(Comparison::Less, 170.0));
+        assert_eq!(parse_term("bpm<=170").unwrap(), Term::Bpm(Comparison::LessEqual, 170.0));
+        assert_eq!(parse_term("bpm:170.5").unwrap(), Term::Bpm(Comparison::Equal, 170.5));
+        assert_eq!(parse_term("bpm=170").unwrap(), Term::Bpm(Comparison::Equal, 170.0));
+        assert_eq!(parse_term("plays>=3").unwrap(), Term::Plays(Comparison::GreaterEqual, 3.0));
+        assert_eq!(parse_term("rating>4").unwrap(), Term::Rating(Comparison::Greater, 4.0));
+        assert_eq!(parse_term("lastplayed>30").unwrap(), Term::LastPlayed(Comparison::Greater, 30.0));
+        assert_eq!(parse_term("fav:yes").unwrap(), Term::Favourite(true));
+        assert_eq!(parse_term("fav:N").unwrap(), Term::Favourite(false));
+    }
+
+    #[test]
+    fn lengths_take_seconds_or_minutes() {
+        assert_eq!(parse_length("90"), Ok(90.0));
+        assert_eq!(parse_length("3:00"), Ok(180.0));
+        assert_eq!(parse_length("1:30.5"), Ok(90.5));
+        assert!(parse_length("3:").is_err());
+        assert!(parse_length("a:00").is_err());
+        assert_eq!(parse_term("length<=2:30").unwrap(), Term::Length(Comparison::LessEqual, 150.0));
+    }
+
+    #[test]
+    fn bad_terms_are_errors() {
+        assert_eq!(parse_term("colour:red").unwrap_err(), "unknown field 'colour'");
+        assert!(parse_term("artist>xi").is_err());
+        assert!(parse_term("fav>yes").is_err());
+        assert!(parse_term("fav:maybe").is_err());
+        assert!(parse_term("bpm>fast").is_err());
+    }
+
+    #[test]
+    fn queries_need_every_term_to_parse() {
+        let query = Query::parse(r#"artist:"camellia xi" bpm>=170 length<3:00 ghost"#).unwrap();
+        assert_eq!(query.terms, vec![
+            Term::Artist(String::from("camellia xi")),
+            Term::Bpm(Comparison::GreaterEqual, 170.0),
+            Term::Length(Comparison::Less, 180.0),
+            Term::Text(String::from("ghost")),
+        ]);
+        assert!(Query::parse("ghost bpm>").is_err());
+        assert!(Query::parse("").unwrap().terms.is_empty());
+    }
+
+    #[test]
+    fn half_typed_fields_search_as_text() {
+        for text in ["bpm>", "length<3:", "artis:x"] {
+            assert!(Query::parse(text).is_err(), "{}", text);
+            assert_eq!(Query::text(text).terms, vec![Term::Text(text.to_string())]);
+        }
+    }
+
+    #[test]
+    fn equal_is_loose_for_bpms() {
+        assert!(Comparison::Equal.check(170.4, 170.0));
+        assert!(!Comparison::Equal.check(170.5, 170.0));
+        assert!(Comparison::LessEqual.check(170.0, 170.0));
+        assert!(!Comparison::Less.check(170.0, 170.0));
+    }
+}
@@ -1,69 +1,128 @@
 use tui::backend::Backend;
 use tui::Frame;
 use tui::layout::{Layout, Direction, Constraint, Rect};
-use tui::widgets::{Paragraph, Borders, BorderType, Block, ListItem, List};
-use tui::style::{Style, Color, Modifier};
+use tui::widgets::{Paragraph, Borders, BorderType, Block, ListItem, List, Clear, BarChart, Chart, Dataset, Axis, GraphType};
+use tui::style::{Style, Modifier};
+use tui::symbols::Marker;
 use tui::text::{Span, Text, Spans};
 use unicode_width::UnicodeWidthStr;
 
-use crate::osu::Mod;
-use crate::player::Playlist;
-use crate::{App, UIMode};
+use crate::click::ClickMode;
+use crate::osu::{Entry, Mod};
+use crate::player::{Playlist, QueueRow, QueueView};
+use crate::library::Library;
+use crate::visualizer::{VisualizerMode, FFT_SIZE, spectrum};
+use crate::query::SortKey;
+use crate::stats::Period;
+use crate::user_data::MAX_RATING;
+use crate::keymap::Section;
+use crate::theme::{Theme, Role};
+use crate::layout::Panel;
+use crate::{App, UIMode, ListInput, POLL_RATE};
+
+/// how many songs or artists each list in the stats popup shows
+const STATS_SHOWN: usize = 5;
+
+/// WHERE EACH PANEL WAS DRAWN LAST FRAME, SO MOUSE CLICKS CAN BE MATCHED UP WITH THEM
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Areas {
+    pub search: Rect,
+    pub songs: Rect,
+    pub playbar: Rect,
+    pub progress: Rect,
+    pub info: Rect,
+    pub playlists: Rect,
+    /// the help popup, for how far it can scroll
+    pub help: Rect,
+}
 
-/// THE GLOBAL RENDERER THAT CALLS EVERYTHING ELSE
-pub fn render<B: Backend>(f: &mut Frame<B>, app: &App) {
-    let all_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .margin(0)
-        .constraints([
-            Constraint::Percentage(60),
-            Constraint::Percentage(40),
-        ]).split(f.size());
-    
-    let left_chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .margin(0)
-        .constraints([
-            Constraint::Length(3),
-            Constraint::Min(1),
-        ]).split(all_chunks[0]);
+impl Areas {
+    fn set(&mut self, panel: Panel, area: Rect) {
+        match panel {
+            Panel::Search => self.search = area,
+            Panel::Songs => self.songs = area,
+            Panel::PlayBar => self.playbar = area,
+            Panel::Progress => self.progress = area,
+            Panel::Info => self.info = area,
+            Panel::Playlists => self.playlists = area,
+            // nothing happens when the visualizer is clicked
+            Panel::Visualizer => {}
+        }
+    }
 
-    let right_chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .margin(0)
-        .constraints([
-            Constraint::Percentage(30),
-            Constraint::Length(5),
-            Constraint::Percentage(70),
-        ]).split(all_chunks[1]);
+    pub fn panel_at(&self, x: u16, y: u16) -> Option<(Panel, Rect)> {
+        [
+            (Panel::Search, self.search),
+            (Panel::Songs, self.songs),
+            (Panel::PlayBar, self.playbar),
+            (Panel::Progress, self.progress),
+            (Panel::Info, self.info),
+            (Panel::Playlists, self.playlists),
+        ].into_iter().find(|(_, r)| x >= r.x && x < r.x + r.width && y >= r.y && y < r.y + r.height)
+    }
+}
 
-    // renders the input block
-    song_input(app, f, left_chunks[0]);
+/// THE GLOBAL RENDERER THAT CALLS EVERYTHING ELSE, GIVES BACK WHERE THE PANELS WENT
+pub fn render<B: Backend>(f: &mut Frame<B>, app: &App) -> Areas {
+    let mut areas = Areas::default();
+    let visualizer_on = app.visualizer != VisualizerMode::Off;
+    for (panel, area) in app.layout.arrange(f.size(), app.focused_panel(), visualizer_on) {
+        match panel {
+            // renders the input block
+            Panel::Search => song_input(app, f, area),
+            // renders the shown songs block
+            Panel::Songs => song_search(app, f, area),
+            Panel::Visualizer => visualizer(app, f, area),
+            // renders the plan to listen bit
+            Panel::PlayBar => playbar(app, f, area),
+            // renders how far through the playing song is
+            Panel::Progress => progress_bar(app, f, area),
+            // renders the information for the songs
+            Panel::Info => song_info(app, f, area),
+            // renders the playlist block including new bar if needed
+            Panel::Playlists => match app.is_adding_list {
+                true => new_playlist_ui(app, f, area),
+                false => basic_playlist(app, f, area),
+            },
+        }
+        areas.set(panel, area);
+    }
 
-    // renders the shown songs block
-    song_search(app, f, left_chunks[1]);
-    
-    // renders the plan to listen bit
-    playbar(app, f, right_chunks[0]);
+    // the typing box normally sits above the playlists, so it goes along the bottom when theyre hidden
+    let typing_at_bottom = app.is_adding_list && areas.playlists == Rect::default();
+    if typing_at_bottom {
+        input_bar(list_input_title(app), app.new_playlist_name.clone(), app.theme.style(Role::Focus), f, f.size());
+    }
 
-    // renders the information for the songs
-    song_info(app, f, right_chunks[1]);
+    if let Some(period) = app.stats_period {
+        stats_popup(app, period, f, f.size());
+    }
 
-    // renders the playlist block including new bar if needed
-    match app.is_adding_list {
-        true => new_playlist_ui(app, f, right_chunks[2]),
-        false => basic_playlist(app, f, right_chunks[2]),
+    if let Some(scroll) = app.help_scroll {
+        areas.help = help_popup(app, scroll, f, f.size());
     }
 
-    // sets the cursor to the input field
-    f.set_cursor(left_chunks[1].x + app.query.width() as u16 + 1, left_chunks[0].y + 1);
-}
+    // destructive actions ask first
+    if let Some(message) = app.confirm_message() {
+        confirm_popup(message, app.theme.style(Role::Focus), f, f.size());
+    }
 
+    // sets the cursor to the input field, or the command line when its open, the bottom box sets its own
+    match &app.command_line.text {
+        Some(text) => command_bar(app, text, f, f.size()),
+        None if areas.search != Rect::default() && !typing_at_bottom => {
+            f.set_cursor(areas.search.x + app.query.width() as u16 + 1, areas.search.y + 1);
+        }
+        None => {}
+    }
+
+    areas
+}
 
 /// RENDERS THE SEARCH BAR FOR THE SONGS
 fn song_input<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect) {
     let paragraph_style = match app.current_ui {
-        UIMode::Input => Style::default().fg(Color::Yellow),
+        UIMode::Input => app.theme.style(Role::Focus),
         _ => Style::default()
     };
 
@@ -73,6 +132,25 @@ fn song_input<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect) {
     f.render_widget(input_block, area);
 }
 
+/// songs can go missing from the library after a rescan
+#[inline]
+fn song_name<'a>(library: &'a Library, id: &str) -> &'a str {
+    library.get(id).map_or("<missing song>", |s| s.song_name.as_str())
+}
+
+/// THE NAME OF THE SONG WITH ITS MOD, NOMOD IS LEFT OFF
+fn entry_name(library: &Library, entry: &Entry) -> String {
+    match (entry.modifier, entry.rate) {
+        (Mod::NoMod, None) => song_name(library, &entry.id).to_string(),
+        _ => format!("{} ({})", song_name(library, &entry.id), entry.tag()),
+    }
+}
+
+#[inline]
+fn format_time(seconds: usize) -> String {
+    format!("{}:{:02}", seconds/60, seconds%60)
+}
+
 #[inline]
 fn song_range(rows: usize, index: usize, songs_len: usize) -> (usize, usize) {
     // if all of the songs can be displayed normally, dont bother
@@ -86,11 +164,22 @@ fn song_range(rows: usize, index: usize, songs_len: usize) -> (usize, usize) {
     }
 
     // the range has to be shifted
-    let height = std::cmp::min(index + (rows/2), songs_len) + 1;
-    return (index - (rows / 2), height)
+    let height = std::cmp::min(index + (rows/2) + 1, songs_len);
+    (index - (rows / 2), height)
     
 }
 
+/// THE INDEX OF THE LIST ITEM DRAWN ON A LINE OF THE SCREEN, SCROLLED THE SAME WAY THE LISTS ARE
+pub fn list_index(area: Rect, y: u16, selected: usize, len: usize) -> Option<usize> {
+    // the borders arent items
+    if y <= area.y || y + 1 >= area.y + area.height {
+        return None;
+    }
+    let (bottom, top) = song_range(area.height.saturating_sub(2) as usize, selected, len);
+    let index = bottom + (y - area.y - 1) as usize;
+    (index < top).then_some(index)
+}
+
 /// RENDERS THE RESULTS FROM THE SEARCH BAR
 fn song_search<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect) {
     if area.height < 3 {
@@ -103,107 +192,288 @@ fn song_search<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect) {
     let used_vec = &app.queried_songs[bottom..top];
     let new_query_i = app.query_i - bottom;
 
-    let list_items: Vec<ListItem> = used_vec.iter().enumerate().map(|(i, song)| {
+    let list_items: Vec<ListItem> = used_vec.iter().enumerate().map(|(i, song_i)| {
+        let song = &app.library.songs()[*song_i];
         let (chosen_symbol, line_style) = 
             if new_query_i == i {
-                (">", Style::default().fg(Color::Red))
+                (">", app.theme.style(Role::Cursor))
             } else {
                 (" ", Style::default())
             };
-        let mod_text = match song.modifier {
-            Mod::NoMod => "<NM>",
-            Mod::DoubleTime => "<DT>",
-            Mod::Nightcore => "<NC>"
-        };
+        let favourite = if app.user_data.is_favourite(&song.id) { "♥" } else { " " };
         let line_content = vec![
-            Span::styled(chosen_symbol, Style::default().fg(Color::Red)),
+            Span::styled(chosen_symbol, app.theme.style(Role::Cursor)),
             Span::raw("|"),
-            Span::styled(mod_text, Style::default().fg(Color::Cyan)),
+            Span::styled(favourite, app.theme.style(Role::Favourite)),
+            Span::styled(rating_stars(app.user_data.rating(&song.id)), app.theme.style(Role::Rating)),
             Span::raw("|"),
             Span::styled(&song.artist, Style::default().add_modifier(Modifier::BOLD)),
             Span::raw(": "),
-            Span::styled(&song.song_name, Style::default().fg(Color::Green)),
+            Span::styled(&song.song_name, app.theme.style(Role::Title)),
             Span::raw(" "),
         ];
         ListItem::new(Text::from(Spans::from(line_content))).style(line_style)
     }).collect();
 
     let style = match app.current_ui {
-        UIMode::Input => Style::default().add_modifier(Modifier::ITALIC).fg(Color::Yellow),
+        UIMode::Input => app.theme.style(Role::Focus).add_modifier(Modifier::ITALIC),
         _ => Style::default().add_modifier(Modifier::ITALIC)
     };
 
+    // songs are added to playlists and the playbar with this mod
+    let mut title = vec![
+        Span::raw("songs |adding as "),
+        Span::styled(app.search_mod.short_name(), app.theme.style(Role::Mods)),
+        Span::raw("|"),
+    ];
+    if app.sort != SortKey::Library {
+        title.push(Span::raw(format!("sort: {}|", app.sort.name())));
+    }
+    let title = Spans::from(title);
     let shown_songs_block = List::new(list_items)
-        .block(Block::default().title("songs").borders(Borders::ALL).border_type(BorderType::Rounded).border_style(style))
+        .block(Block::default().title(title).borders(Borders::ALL).border_type(BorderType::Rounded).border_style(style))
         .highlight_symbol(">>");
     f.render_widget(shown_songs_block, area);
 }
 
+/// filled stars for the rating then empty ones, unrated songs get blanks so the columns line up
+fn rating_stars(rating: u8) -> String {
+    if rating == 0 {
+        return " ".repeat(MAX_RATING as usize);
+    }
+    let filled = rating.min(MAX_RATING) as usize;
+    format!("{}{}", "★".repeat(filled), "☆".repeat(MAX_RATING as usize - filled))
+}
+
+/// RENDERS THE SPECTRUM BARS OR THE WAVEFORM OF WHATS PLAYING
+fn visualizer<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect) {
+    if area.width < 3 || area.height < 3 {
+        return;
+    }
+    let block = Block::default().title("visualizer").borders(Borders::ALL).border_type(BorderType::Rounded);
+
+    let (samples, sample_rate) = match app.player.samples.lock() {
+        Ok(b) => (b.latest(FFT_SIZE), b.sample_rate),
+        Err(_) => return,
+    };
+
+    match app.visualizer {
+        VisualizerMode::Bars => {
+            // each bar is two wide with a gap of one
+            let bar_count = (area.width as usize - 2) / 3;
+            let heights = spectrum(&samples, bar_count);
+            let data: Vec<(&str, u64)> = heights.into_iter().map(|h| ("", h)).collect();
+            let chart = BarChart::default()
+                .block(block)
+                .data(&data)
+                .bar_width(2)
+                .bar_gap(1)
+                .max(100)
+                .bar_style(app.theme.style(Role::Visualizer));
+            f.render_widget(chart, area);
+        }
+        VisualizerMode::Wave => {
+            // only show what was played since the last redraw
+            let window = (sample_rate as f64 * POLL_RATE.as_secs_f64()) as usize;
+            let start = samples.len().saturating_sub(window);
+            let points: Vec<(f64, f64)> = samples[start..].iter().enumerate().map(|(i, s)| {
+                (i as f64, *s as f64)
+            }).collect();
+            let dataset = Dataset::default()
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(app.theme.style(Role::Visualizer))
+                .data(&points);
+            let chart = Chart::new(vec![dataset])
+                .block(block)
+                .x_axis(Axis::default().bounds([0.0, window.max(1) as f64]))
+                .y_axis(Axis::default().bounds([-1.0, 1.0]));
+            f.render_widget(chart, area);
+        }
+        VisualizerMode::Off => {}
+    }
+}
+
 /// RENDERS THE CURRENTLY LISTENING TO BIT
 fn playbar<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect) {
-    let playlist_items: Vec<ListItem> = app.player.current_songs.iter().enumerate().map(|(i, s)| {
-        let symbol = if i == app.player.hovered_index {
+    let rows = app.player.rows(&app.playlists);
+    let (bottom, top) = song_range(area.height.saturating_sub(2) as usize, app.player.hovered_index, rows.len());
+    let playlist_items: Vec<ListItem> = rows[bottom..top].iter().enumerate().map(|(i, row)| {
+        let symbol = if i + bottom == app.player.hovered_index {
             ">"
         } else {
             " "
         };
 
-        let style = if i == app.player.playing_index {
-            Style::default().fg(Color::Green)
-        } else {
-            Style::default()
+        // songs still to come from the playlist are dimmer than ones queued by hand
+        let style = match row {
+            QueueRow::Playing => app.theme.style(Role::Title),
+            QueueRow::Playlist(_) => app.theme.style(Role::Dim),
+            _ => Style::default(),
+        };
+        let name = match app.player.row_entry(*row, &app.playlists) {
+            Some(entry) => entry_name(&app.library, entry),
+            None => String::new(),
         };
 
         let content = vec![
-            Span::styled(symbol, Style::default().fg(Color::Red)),
+            Span::styled(symbol, app.theme.style(Role::Cursor)),
             Span::raw("|"),
-            Span::styled(&s.song_name, style),
+            Span::styled(name, style),
         ];
         ListItem::new(Text::from(Spans::from(content)))
     }).collect();
 
-    let style = match app.current_ui {
-        UIMode::PlayBar => Style::default().fg(Color::Yellow),
+    let mut style = match app.current_ui {
+        UIMode::PlayBar => app.theme.style(Role::Focus),
         _ => Style::default()
     };
 
+    // the border pulses at the start of every beat
+    let position_ms = app.player.position().as_secs_f64() * 1000.0;
+    let on_beat = app.playing_song()
+        .and_then(|s| s.beat_progress(position_ms))
+        .is_some_and(|p| p < 0.15);
+    let border_type = match on_beat {
+        true => {
+            style = style.add_modifier(Modifier::BOLD);
+            BorderType::Thick
+        }
+        false => BorderType::Rounded,
+    };
+
+    let mut title = match app.player.view {
+        QueueView::UpNext => String::from("up next"),
+        QueueView::History => String::from("history"),
+    };
+    if let Some(p) = app.player.playing_playlist.and_then(|i| app.playlists.get(i)) {
+        title.push_str(&format!(" |from: {}|", p.name));
+    }
+    if !matches!(app.player.click_mode, ClickMode::Off) {
+        title.push_str(&format!(" |click: {}|", app.player.click_mode.name()));
+    }
+    if app.player.repeat_one {
+        title.push_str(" |repeat one|");
+    }
     let playlist_block = List::new(playlist_items)
-        .block(Block::default().border_style(style).title("currently playing").borders(Borders::ALL).border_type(BorderType::Rounded));
+        .block(Block::default().border_style(style).title(title).borders(Borders::ALL).border_type(border_type));
     f.render_widget(playlist_block, area);
 }
 
+/// RENDERS HOW FAR THROUGH THE PLAYING SONG IS, KIAI SECTIONS ARE HIGHLIGHTED
+fn progress_bar<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect) {
+    let block = Block::default().borders(Borders::ALL).border_type(BorderType::Rounded);
+    let song = match app.playing_song() {
+        Some(s) => s,
+        None => {
+            f.render_widget(Paragraph::new("nothing playing").block(block), area);
+            return;
+        }
+    };
+
+    let speed = app.player.playing_entry().map_or(1.0, |e| e.speed()) as f64;
+    let position = app.player.position().as_secs_f64();
+    let length = song.length.max(1) as f64;
+    let mut title = format!(
+        "{} |{}/{}|", 
+        song.song_name, 
+        format_time((position / speed) as usize), 
+        format_time((length / speed) as usize)
+    );
+    if let Some(bpm) = playing_bpm(app) {
+        title = format!("{} {:.0} BPM |", title, bpm);
+    }
+
+    let width = area.width.saturating_sub(2) as usize;
+    let played = (position / length * width as f64) as usize;
+    let kiai = song.kiai_sections();
+    let cells: Vec<Span> = (0..width).map(|i| {
+        let cell_ms = (i as f64 + 0.5) / width as f64 * length * 1000.0;
+        let in_kiai = kiai.iter().any(|(s, e)| cell_ms >= *s as f64 && cell_ms < *e as f64);
+        let symbol = if i < played { "━" } else { "─" };
+        let style = match (in_kiai, i < played) {
+            (true, _) => app.theme.style(Role::Kiai),
+            (false, true) => app.theme.style(Role::Played),
+            (false, false) => Style::default(),
+        };
+        Span::styled(symbol, style)
+    }).collect();
+
+    let bar = Paragraph::new(Spans::from(cells)).block(block.title(title));
+    f.render_widget(bar, area);
+}
+
+/// THE BPM OF THE PLAYING SONG WHERE IT IS NOW, THE ONE HEARD SO THE MOD IS APPLIED
+fn playing_bpm(app: &App) -> Option<f64> {
+    let speed = app.player.playing_entry()?.speed() as f64;
+    let bpm = app.playing_song()?.bpm_at(app.player.position().as_secs_f64() * 1000.0)?;
+    Some(bpm * speed)
+}
+
 /// RENDERS THE SONG INFORMATION
 fn song_info<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect) {
     let to_raw_listitem = |i| {ListItem::new(Text::from(Spans::from(Span::raw(i))))};
     
-    let content = if app.queried_songs.len() == 0 {
-        vec![to_raw_listitem(format!("No song selected"))]
+    let mut content = if app.queried_song().is_none() {
+        vec![to_raw_listitem(String::from("No song selected"))]
     } else {
 
-        let song = app.queried_songs[app.query_i].clone();
-        let length_mult = match song.modifier { 
-            Mod::NoMod => 1.0,
-            _ => 1.5,
-        };
+        let song = app.queried_song().unwrap();
+        let length_mult = app.search_mod.speed() as f64;
         
         let modded_length = (song.length as f64 / length_mult) as usize;
-        let formatted_length = format!("{}:{:02}", modded_length/60, modded_length%60);
+        let formatted_length = format_time(modded_length);
+        let formatted_bpm = match song.main_bpm() {
+            Some(bpm) => format!("{:.0}", bpm * length_mult),
+            None => String::from("unknown"),
+        };
+        let plays = app.stats.song(&song.id).map_or(0, |s| s.play_count);
+        let favourite = if app.user_data.is_favourite(&song.id) { " ♥" } else { "" };
         vec![
-            to_raw_listitem(format!("TITLE: {}", &song.song_name)),
+            to_raw_listitem(format!("TITLE: {}{}", &song.song_name, favourite)),
             to_raw_listitem(format!("ARTIST: {}", &song.artist)),
-            to_raw_listitem(format!("LENGTH: {}", &formatted_length)),
+            to_raw_listitem(format!("LENGTH: {} |PLAYS: {}", &formatted_length, plays)),
+            to_raw_listitem(format!("BPM: {} |RATING: {}", &formatted_bpm, rating_stars(app.user_data.rating(&song.id)).trim_end())),
+            to_raw_listitem(format!("TAGS: {}", app.user_data.tags(&song.id).join(", "))),
+            to_raw_listitem(format!("NOTE: {}", app.user_data.note(&song.id))),
         ]
     };
+    // the selected song above is whatever is hovered in search, this is what can be heard
+    if let Some(bpm) = playing_bpm(app) {
+        content.push(to_raw_listitem(format!("NOW PLAYING: {:.0} BPM", bpm)));
+    }
+    // messages from exports and the like go in the title
+    let title = match app.status.is_empty() {
+        true => String::from("info"),
+        false => format!("info |{}|", app.status),
+    };
     let info_block = List::new(content)
         .block(
             Block::default()
-            .title("info")
+            .title(title)
             .borders(Borders::ALL)
             .border_type(BorderType::Rounded)
         );
     f.render_widget(info_block, area);
 }
 
+/// WHAT THE TYPING BOX IS FOR
+fn list_input_title(app: &App) -> String {
+    let song_title = |prefix: &str, id: &str| {
+        let name = app.library.get(id).map_or(id, |s| s.song_name.as_str());
+        format!("{} {}", prefix, name)
+    };
+    match &app.list_input {
+        ListInput::New => String::from("new playlist name"),
+        ListInput::Rename(_) => String::from("rename playlist"),
+        ListInput::Import => String::from("import playlist from (.m3u8 / .xspf)"),
+        ListInput::Smart => String::from("smart playlist query"),
+        ListInput::EditQuery(_) => String::from("edit smart playlist query"),
+        ListInput::Tags(id) => song_title("tags for", id),
+        ListInput::Note(id) => song_title("note on", id),
+    }
+}
+
 /// RENDERS THE PLAYLIST WHEN NEW INPUT IS ADDED
 fn new_playlist_ui<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect) {
     let playlist_chunk = Layout::default()
@@ -213,10 +483,10 @@ fn new_playlist_ui<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect) {
             Constraint::Length(3),
             Constraint::Percentage(70),
         ]).split(area);
-    
+
     let input_block = Paragraph::new(app.new_playlist_name.clone())
-        .style(Style::default().fg(Color::Yellow))
-        .block(Block::default().borders(Borders::ALL).border_type(BorderType::Rounded).title("new playlist name"));
+        .style(app.theme.style(Role::Focus))
+        .block(Block::default().borders(Borders::ALL).border_type(BorderType::Rounded).title(list_input_title(app)));
     f.render_widget(input_block, playlist_chunk[0]);
     basic_playlist(app, f, playlist_chunk[1]);
 }
@@ -224,22 +494,42 @@ fn new_playlist_ui<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect) {
 /// RENDERS THE PLAYLIST OTHERWISE, SOMETIMES DOES SONGS INSIDE PLAYLIST
 fn basic_playlist<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect) {
     let style = match app.current_ui {
-        UIMode::Playlist => Style::default().fg(Color::Yellow),
+        UIMode::Playlist => app.theme.style(Role::Focus),
         _ => Style::default(),
     };
 
-    let list = match app.player.current_playlist.is_none() {
-        true => get_outer_playlist(&app.playlists, style),
-        false => get_inner_playlist(app.player.current_playlist.unwrap(), style)
+    let rows = area.height.saturating_sub(2) as usize;
+    let list = match app.player.current_playlist {
+        None => get_outer_playlist(&app.playlists, app.playlist_i, rows, style, &app.theme),
+        Some(i) => get_inner_playlist(&app.playlists[i], &app.library, app.playlist_song_i, app.grabbed_song, rows, style, &app.theme)
     };
     f.render_widget(list, area)
 }
 
+/// A LINE IN ONE OF THE PLAYLIST VIEWS, WITH THE CURSOR IF ITS SELECTED
+fn playlist_line<'a>(text: String, selected: bool, grabbed: bool, theme: &Theme) -> ListItem<'a> {
+    let (symbol, line_style) = match (selected, grabbed) {
+        (true, true) => ("=", theme.style(Role::Cursor).add_modifier(Modifier::BOLD)),
+        (true, false) => (">", theme.style(Role::Cursor)),
+        _ => (" ", Style::default()),
+    };
+    let content = vec![
+        Span::styled(symbol, theme.style(Role::Cursor)),
+        Span::raw("|"),
+        Span::raw(text),
+    ];
+    ListItem::new(Text::from(Spans::from(content))).style(line_style)
+}
+
 /// RETURNS LIST OF SONGS INSIDE PLAYLIST
-fn get_inner_playlist<'a>(playlist: &Playlist, style: Style) -> List<'a> {
-    let title = format!("name: {} |shuffle: {} |repeat: {} |", playlist.name, playlist.shuffle_on, playlist.repeat_on);
-    let list_items: Vec<ListItem> = playlist.songs.iter().map(|s| {
-        ListItem::new(Text::from(Spans::from(Span::raw(s.song_name.clone()))))
+fn get_inner_playlist<'a>(playlist: &Playlist, library: &Library, selected: usize, grabbed: bool, rows: usize, style: Style, theme: &Theme) -> List<'a> {
+    let mut title = format!("name: {} |shuffle: {} |repeat: {} |", playlist.name, playlist.shuffle_on, playlist.repeat_on);
+    if let Some(query) = &playlist.query {
+        title.push_str(&format!("query: {} |", query));
+    }
+    let (bottom, top) = song_range(rows, selected, playlist.songs.len());
+    let list_items: Vec<ListItem> = playlist.songs[bottom..top].iter().enumerate().map(|(i, entry)| {
+        playlist_line(entry_name(library, entry), i + bottom == selected, grabbed, theme)
     }).collect();
 
     let playlist_block = List::new(list_items)
@@ -253,11 +543,17 @@ fn get_inner_playlist<'a>(playlist: &Playlist, style: Style) -> List<'a> {
 }
 
 /// RETURNS LIST OF PLAYLIST
-fn get_outer_playlist<'a>(playlists: &Vec<Playlist>, style: Style) -> List<'a> {
+fn get_outer_playlist<'a>(playlists: &[Playlist], selected: usize, rows: usize, style: Style, theme: &Theme) -> List<'a> {
     let title = "playlists";
 
-    let list_items: Vec<ListItem> = playlists.iter().map(|p| {
-        ListItem::new(Text::from(Spans::from(Span::raw(p.name.clone()))))
+    let (bottom, top) = song_range(rows, selected, playlists.len());
+    let list_items: Vec<ListItem> = playlists[bottom..top].iter().enumerate().map(|(i, p)| {
+        // smart playlists are marked so its clear they cant be edited by hand
+        let text = match p.is_smart() {
+            true => format!("~ {} ({})", p.name, p.songs.len()),
+            false => p.name.clone(),
+        };
+        playlist_line(text, i + bottom == selected, false, theme)
     }).collect();
 
     let playlist_block = List::new(list_items)
@@ -268,4 +564,129 @@ fn get_outer_playlist<'a>(playlists: &Vec<Playlist>, style: Style) -> List<'a> {
         .title(title)
     );
     playlist_block
-}
\ No newline at end of file
+}
+
+/// A RECT OF THE GIVEN SIZE IN THE MIDDLE OF THE AREA
+fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// HOURS AND MINUTES FOR LONGER STRETCHES OF TIME
+fn format_duration(seconds: f64) -> String {
+    let minutes = (seconds / 60.0) as u64;
+    match minutes / 60 {
+        0 => format!("{}m", minutes),
+        hours => format!("{}h {}m", hours, minutes % 60),
+    }
+}
+
+/// RENDERS THE LISTENING STATS FOR THE PERIOD OVER EVERYTHING ELSE
+fn stats_popup<B: Backend>(app: &App, period: Period, f: &mut Frame<B>, area: Rect) {
+    let heading = |text: &str| Spans::from(Span::styled(text.to_string(), app.theme.style(Role::Heading)));
+    let ranked = |rank: usize, name: &str, count: u32| Spans::from(vec![
+        Span::raw(format!(" {}. ", rank + 1)),
+        Span::styled(name.to_string(), app.theme.style(Role::Title)),
+        Span::raw(format!(" ({})", count)),
+    ]);
+
+    let mut lines = vec![
+        Spans::from(format!("listened for {}", format_duration(app.stats.total_seconds(period)))),
+        Spans::from(""),
+        heading("top songs"),
+    ];
+    for (i, (id, count)) in app.stats.top_songs(period, STATS_SHOWN).iter().enumerate() {
+        lines.push(ranked(i, song_name(&app.library, id), *count));
+    }
+    lines.push(Spans::from(""));
+    lines.push(heading("top artists"));
+    for (i, (artist, count)) in app.stats.top_artists(period, &app.library, STATS_SHOWN).iter().enumerate() {
+        lines.push(ranked(i, artist, *count));
+    }
+    lines.push(Spans::from(""));
+    lines.push(heading("most skipped"));
+    for (i, (id, count)) in app.stats.most_skipped(period, STATS_SHOWN).iter().enumerate() {
+        lines.push(ranked(i, song_name(&app.library, id), *count));
+    }
+
+    let title = format!("stats |{}| <- ->", period.name());
+    let popup_area = centered_rect(60, lines.len() as u16 + 2, area);
+    let popup = Paragraph::new(lines)
+        .block(Block::default().title(title).borders(Borders::ALL).border_type(BorderType::Rounded));
+    f.render_widget(Clear, popup_area);
+    f.render_widget(popup, popup_area);
+}
+
+/// RENDERS EVERY KEY THAT WORKS IN THE FOCUSED PANEL, STRAIGHT FROM THE KEYMAP
+fn help_popup<B: Backend>(app: &App, scroll: usize, f: &mut Frame<B>, area: Rect) -> Rect {
+    let section = app.key_section();
+    let bindings = app.keymap.bindings(section);
+    let keys: Vec<String> = bindings.iter()
+        .map(|(_, keys)| keys.iter().map(|k| k.to_string()).collect::<Vec<_>>().join(", "))
+        .collect();
+    let key_width = keys.iter().map(|k| k.width()).max().unwrap_or(0);
+
+    let mut lines: Vec<Spans> = bindings.iter().zip(keys).map(|((action, _), keys)| Spans::from(vec![
+        Span::styled(format!(" {:<width$}  ", keys, width = key_width), app.theme.style(Role::Mods)),
+        Span::raw(action.description()),
+    ])).collect();
+    if section == Section::Search {
+        lines.push(Spans::from(Span::styled(" anything else is typed into the search", app.theme.style(Role::Dim))));
+    }
+
+    let width = lines.iter().map(|l| l.width()).max().unwrap_or(0) as u16 + 3;
+    let popup_area = centered_rect(width.max(30), lines.len() as u16 + 2, area);
+    // the scroll stops once the last line is showing
+    let shown = popup_area.height.saturating_sub(2) as usize;
+    let scroll = scroll.min(lines.len().saturating_sub(shown)) as u16;
+    let popup = Paragraph::new(lines)
+        .scroll((scroll, 0))
+        .block(Block::default().title(format!("keys |{}|", section.name())).borders(Borders::ALL).border_type(BorderType::Rounded));
+    f.render_widget(Clear, popup_area);
+    f.render_widget(popup, popup_area);
+    popup_area
+}
+
+/// RENDERS THE : LINE ALONG THE BOTTOM WITH WHAT TAB IS GOING THROUGH IN THE TITLE
+fn command_bar<B: Backend>(app: &App, text: &str, f: &mut Frame<B>, area: Rect) {
+    let title = match app.command_line.options() {
+        [] => String::from("command"),
+        options => format!("command |{}|", options.join(" ")),
+    };
+    input_bar(title, format!(":{}", text), Style::default(), f, area);
+}
+
+/// RENDERS A BOX BEING TYPED IN ALONG THE BOTTOM OF THE AREA WITH THE CURSOR AT THE END
+fn input_bar<B: Backend>(title: String, line: String, style: Style, f: &mut Frame<B>, area: Rect) {
+    let bar_area = Rect {
+        y: area.y + area.height.saturating_sub(3),
+        height: area.height.min(3),
+        ..area
+    };
+    // long lines scroll so the end being typed stays in view
+    let line_width = line.width() as u16;
+    let scroll = line_width.saturating_sub(bar_area.width.saturating_sub(3));
+    let bar = Paragraph::new(line)
+        .style(style)
+        .scroll((0, scroll))
+        .block(Block::default().title(title).borders(Borders::ALL).border_type(BorderType::Rounded));
+    f.render_widget(Clear, bar_area);
+    f.render_widget(bar, bar_area);
+    f.set_cursor(bar_area.x + 1 + line_width - scroll, bar_area.y + 1);
+}
+
+/// RENDERS THE YES OR NO PROMPT OVER EVERYTHING ELSE
+fn confirm_popup<B: Backend>(message: String, style: Style, f: &mut Frame<B>, area: Rect) {
+    let popup_area = centered_rect(message.width() as u16 + 4, 3, area);
+    let popup = Paragraph::new(message)
+        .style(style)
+        .block(Block::default().title("confirm").borders(Borders::ALL).border_type(BorderType::Rounded));
+    f.render_widget(Clear, popup_area);
+    f.render_widget(popup, popup_area);
+}
@@ -1,12 +1,63 @@
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
 use serde::{Serialize, Deserialize};
 
-pub fn serialize<T: Serialize>(data: &T, path: &str) {
-    let serialized_data = serde_json::to_string_pretty(data).unwrap();
-    fs::write(path, serialized_data).unwrap();
+/// WRITES TO A TEMP FILE FIRST SO A CRASH HALFWAY THROUGH CANT CORRUPT THE REAL ONE
+pub fn serialize<T: Serialize>(data: &T, path: &str) -> io::Result<()> {
+    let serialized_data = serde_json::to_string_pretty(data)?;
+    let temp_path = format!("{}.tmp", path);
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(serialized_data.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(temp_path, path)
+}
+
+/// SHIFTS THE OLD BACKUPS ALONG AND COPIES THE CURRENT FILE TO path.1
+pub fn rotate_backups(path: &str, count: usize) -> io::Result<()> {
+    if count == 0 || !Path::new(path).exists() {
+        return Ok(());
+    }
+    for i in (1..count).rev() {
+        let older = format!("{}.{}", path, i);
+        if Path::new(&older).exists() {
+            fs::rename(&older, format!("{}.{}", path, i + 1))?;
+        }
+    }
+    fs::copy(path, format!("{}.1", path))?;
+    Ok(())
+}
+
+pub fn deserialize<T: for<'a> Deserialize<'a>>(path: &str) -> io::Result<T> {
+    let data = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+/// FOR THE FILES USERS WRITE THEMSELVES, NONE WHEN THERES NO FILE AND AN ERROR TO SHOW WHEN ITS BROKEN
+pub fn load_config<T: for<'a> Deserialize<'a>>(path: &str) -> Result<Option<T>, String> {
+    match deserialize(path) {
+        Ok(config) => Ok(Some(config)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("couldn't read {}: {}", path, e)),
+    }
 }
 
-pub fn deserialize<T: for<'a> Deserialize<'a>>(path: &str) -> Result<T, serde_json::Error> {
-    let data = fs::read_to_string(path).unwrap();
-    serde_json::from_str(&data)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_configs_are_none_and_broken_ones_are_errors() {
+        let path = std::env::temp_dir().join(format!("sposu_config_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert_eq!(load_config::<Vec<u8>>(path), Ok(None));
+        fs::write(path, "[1, 2").unwrap();
+        let broken = load_config::<Vec<u8>>(path);
+        fs::write(path, "[1, 2]").unwrap();
+        let loaded = load_config::<Vec<u8>>(path);
+        fs::remove_file(path).unwrap();
+        assert!(broken.unwrap_err().starts_with(&format!("couldn't read {}", path)));
+        assert_eq!(loaded, Ok(Some(vec![1, 2])));
+    }
 }
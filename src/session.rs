@@ -0,0 +1,91 @@
+use std::io;
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::library::Library;
+use crate::osu::Entry;
+use crate::player::{Player, Playlist, PlaybackState};
+use crate::serialize;
+
+/// only the most recent part of the history is worth keeping between sessions
+const HISTORY_KEPT: usize = 100;
+
+/// the playlist that was loaded into the playbar, the name is checked in case the playlists changed
+#[derive(Serialize, Deserialize)]
+struct SessionPlaylist {
+    index: usize,
+    name: String,
+    state: PlaybackState,
+}
+
+/// EVERYTHING IN THE PLAYBAR WHEN THE APP WAS CLOSED
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    playing: Option<Entry>,
+    /// in seconds of the songs own time
+    #[serde(default)]
+    position: f64,
+    #[serde(default)]
+    queue: Vec<Entry>,
+    #[serde(default)]
+    history: Vec<Entry>,
+    #[serde(default)]
+    playlist: Option<SessionPlaylist>,
+    volume: f32,
+    #[serde(default)]
+    repeat_one: bool,
+}
+
+impl Session {
+    pub fn capture(player: &Player, playlists: &[Playlist]) -> Self {
+        let playlist = player.playing_playlist.and_then(|i| playlists.get(i).map(|p| (i, p)));
+        let history_start = player.history.len().saturating_sub(HISTORY_KEPT);
+        Self {
+            playing: player.playing_entry().cloned(),
+            position: player.position().as_secs_f64(),
+            queue: player.queue.clone(),
+            history: player.history[history_start..].to_vec(),
+            playlist: playlist.map(|(index, p)| SessionPlaylist {
+                index,
+                name: p.name.clone(),
+                state: p.playback_state(),
+            }),
+            volume: player.volume,
+            repeat_one: player.repeat_one,
+        }
+    }
+
+    /// PUTS THE PLAYBAR BACK HOW IT WAS, THE SONG STARTS PAUSED
+    pub fn restore(self, player: &mut Player, library: &Library, playlists: &mut [Playlist]) {
+        player.volume = self.volume;
+        player.repeat_one = self.repeat_one;
+        player.queue = self.queue;
+        player.history = self.history;
+
+        // the playlist might have moved if the file was edited, so its found by name if needed
+        if let Some(saved) = self.playlist {
+            let index = match playlists.get(saved.index) {
+                Some(p) if p.name == saved.name => Some(saved.index),
+                _ => playlists.iter().position(|p| p.name == saved.name),
+            };
+            if let Some(i) = index {
+                playlists[i].restore_playback(saved.state);
+                player.playing_playlist = Some(i);
+            }
+        }
+
+        if let Some(entry) = self.playing {
+            player.resume(library, entry, Duration::from_secs_f64(self.position.max(0.0)));
+        }
+    }
+}
+
+pub fn save_session(session: &Session, path: &str) -> io::Result<()> {
+    serialize::serialize(session, path)
+}
+
+/// A MISSING OR BROKEN SESSION JUST MEANS STARTING FRESH
+pub fn load_session(path: &str) -> Option<Session> {
+    serialize::deserialize(path).ok()
+}
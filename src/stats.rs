@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Serialize, Deserialize};
+
+use crate::library::Library;
+use crate::osu::SongId;
+
+pub const DAY: u64 = 60 * 60 * 24;
+
+/// SECONDS SINCE THE UNIX EPOCH, WHICH IS HOW EVERY PLAY IS TIMESTAMPED
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// one listen of a song, written as a line of the plays file when it stops
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Play {
+    pub id: SongId,
+    /// when it started playing
+    pub started: u64,
+    /// real time, so a DT play of a 3 minute song is 2 minutes
+    pub seconds: f64,
+    pub modifier: String,
+    /// false if it was skipped before the end
+    pub completed: bool,
+}
+
+/// the totals for one song, kept up to date as plays come in
+#[derive(Clone, Debug, Default)]
+pub struct SongStats {
+    pub play_count: u32,
+    pub skips: u32,
+    pub last_played: u64,
+}
+
+/// how far back the stats view looks
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Period {
+    Week,
+    Month,
+    AllTime,
+}
+
+impl Period {
+    pub fn next(&self) -> Self {
+        match self {
+            Period::Week => Period::Month,
+            Period::Month => Period::AllTime,
+            Period::AllTime => Period::Week,
+        }
+    }
+
+    pub fn previous(&self) -> Self {
+        match self {
+            Period::Week => Period::AllTime,
+            Period::Month => Period::Week,
+            Period::AllTime => Period::Month,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Period::Week => "this week",
+            Period::Month => "this month",
+            Period::AllTime => "all time",
+        }
+    }
+
+    fn since(&self, now: u64) -> u64 {
+        match self {
+            Period::Week => now.saturating_sub(7 * DAY),
+            Period::Month => now.saturating_sub(30 * DAY),
+            Period::AllTime => 0,
+        }
+    }
+}
+
+/// EVERY PLAY THERE HAS BEEN, LOADED FROM AND APPENDED TO THE PLAYS FILE
+#[derive(Default)]
+pub struct Stats {
+    plays: Vec<Play>,
+    songs: HashMap<SongId, SongStats>,
+}
+
+impl Stats {
+    /// lines which cant be read are skipped so one bad write doesnt lose everything
+    pub fn load(path: &str) -> Self {
+        let mut stats = Stats::default();
+        let content = fs::read_to_string(path).unwrap_or_default();
+        for play in content.lines().filter_map(|l| serde_json::from_str::<Play>(l).ok()) {
+            stats.add(play);
+        }
+        stats
+    }
+
+    /// ADDS THE PLAY AND APPENDS IT TO THE FILE, THE FILE IS NEVER REWRITTEN
+    pub fn record(&mut self, play: Play, path: &str) -> io::Result<()> {
+        let line = serde_json::to_string(&play)?;
+        self.add(play);
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", line)
+    }
+
+    fn add(&mut self, play: Play) {
+        let song = self.songs.entry(play.id.clone()).or_default();
+        match play.completed {
+            true => song.play_count += 1,
+            false => song.skips += 1,
+        }
+        song.last_played = song.last_played.max(play.started);
+        self.plays.push(play);
+    }
+
+    pub fn song(&self, id: &str) -> Option<&SongStats> {
+        self.songs.get(id)
+    }
+
+    fn plays_since(&self, period: Period) -> impl Iterator<Item = &Play> {
+        let since = period.since(now());
+        self.plays.iter().filter(move |p| p.started >= since)
+    }
+
+    pub fn total_seconds(&self, period: Period) -> f64 {
+        self.plays_since(period).map(|p| p.seconds).sum()
+    }
+
+    /// THE MOST PLAYED SONGS IN THE PERIOD, ONLY PLAYS THAT GOT TO THE END COUNT
+    pub fn top_songs(&self, period: Period, count: usize) -> Vec<(SongId, u32)> {
+        let mut counts: HashMap<&SongId, u32> = HashMap::new();
+        for play in self.plays_since(period).filter(|p| p.completed) {
+            *counts.entry(&play.id).or_default() += 1;
+        }
+        top(counts.into_iter().map(|(id, c)| (id.clone(), c)).collect(), count)
+    }
+
+    /// songs which have gone missing from the library arent counted towards any artist
+    pub fn top_artists(&self, period: Period, library: &Library, count: usize) -> Vec<(String, u32)> {
+        let mut counts: HashMap<&str, u32> = HashMap::new();
+        for play in self.plays_since(period).filter(|p| p.completed) {
+            if let Some(song) = library.get(&play.id) {
+                *counts.entry(&song.artist).or_default() += 1;
+            }
+        }
+        top(counts.into_iter().map(|(a, c)| (a.to_string(), c)).collect(), count)
+    }
+
+    pub fn most_skipped(&self, period: Period, count: usize) -> Vec<(SongId, u32)> {
+        let mut counts: HashMap<&SongId, u32> = HashMap::new();
+        for play in self.plays_since(period).filter(|p| !p.completed) {
+            *counts.entry(&play.id).or_default() += 1;
+        }
+        top(counts.into_iter().map(|(id, c)| (id.clone(), c)).collect(), count)
+    }
+}
+
+/// highest counts first, ties are alphabetical so the list doesnt jump around
+fn top(mut counts: Vec<(String, u32)>, count: usize) -> Vec<(String, u32)> {
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(count);
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osu::Song;
+    use crate::query::{Query, QueryContext};
+    use crate::user_data::UserData;
+
+    fn play(id: &str, days_ago: u64, completed: bool) -> Play {
+        Play {
+            id: id.to_string(),
+            started: now() - days_ago * DAY,
+            seconds: 60.0,
+            modifier: String::from("NM"),
+            completed,
+        }
+    }
+
+    fn stats(plays: Vec<Play>) -> Stats {
+        let mut stats = Stats::default();
+        for p in plays {
+            stats.add(p);
+        }
+        stats
+    }
+
+    #[test]
+    fn adding_plays_keeps_the_totals() {
+        let stats = stats(vec![play("a", 3, true), play("a", 1, false), play("a", 5, true)]);
+        let song = stats.song("a").unwrap();
+        assert_eq!(song.play_count, 2);
+        assert_eq!(song.skips, 1);
+        // the latest play wins even when an older one is added after it
+        assert_eq!(song.last_played, stats.plays[1].started);
+        assert!(stats.song("b").is_none());
+    }
+
+    #[test]
+    fn top_sorts_by_count_then_name() {
+        let counts = vec![(String::from("b"), 2), (String::from("c"), 5), (String::from("a"), 2), (String::from("d"), 1)];
+        assert_eq!(top(counts, 3), vec![(String::from("c"), 5), (String::from("a"), 2), (String::from("b"), 2)]);
+        assert!(top(Vec::new(), 3).is_empty());
+    }
+
+    #[test]
+    fn top_songs_only_count_completed_plays() {
+        let stats = stats(vec![play("a", 0, true), play("b", 0, true), play("b", 0, true), play("c", 0, false), play("c", 0, false)]);
+        assert_eq!(stats.top_songs(Period::AllTime, 10), vec![(String::from("b"), 2), (String::from("a"), 1)]);
+        assert_eq!(stats.most_skipped(Period::AllTime, 10), vec![(String::from("c"), 2)]);
+        assert_eq!(stats.top_songs(Period::AllTime, 1), vec![(String::from("b"), 2)]);
+    }
+
+    #[test]
+    fn periods_leave_out_older_plays() {
+        let stats = stats(vec![play("a", 1, true), play("b", 10, true), play("c", 40, true), play("c", 40, false)]);
+        let ids = |period| stats.top_songs(period, 10).into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(ids(Period::Week), vec!["a"]);
+        assert_eq!(ids(Period::Month), vec!["a", "b"]);
+        assert_eq!(ids(Period::AllTime), vec!["a", "b", "c"]);
+        assert!(stats.most_skipped(Period::Month, 10).is_empty());
+        assert_eq!(stats.total_seconds(Period::Week), 60.0);
+        assert_eq!(stats.total_seconds(Period::AllTime), 240.0);
+    }
+
+    #[test]
+    fn queries_use_play_counts_and_last_played() {
+        let song = |id: &str| Song {
+            id: id.to_string(),
+            audio_path: String::new(),
+            song_name: String::new(),
+            artist: String::new(),
+            length: 0,
+            beatmap_path: String::new(),
+            timing_points: Vec::new(),
+        };
+        let stats = stats(vec![play("a", 2, true), play("a", 2, true), play("b", 20, true), play("c", 1, false)]);
+        let user_data = UserData::default();
+        let context = QueryContext::new(&stats, &user_data);
+        let matching = |query: &str| {
+            let query = Query::parse(query).unwrap();
+            ["a", "b", "c", "never"].into_iter().filter(|id| query.matches(&song(id), &context)).collect::<Vec<_>>()
+        };
+        assert_eq!(matching("plays>=2"), vec!["a"]);
+        assert_eq!(matching("plays:0"), vec!["c", "never"]);
+        assert_eq!(matching("lastplayed<7"), vec!["a", "c"]);
+        // never played counts as longer ago than anything
+        assert_eq!(matching("lastplayed>7"), vec!["b", "never"]);
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::env;
+
+use serde::Deserialize;
+use tui::style::{Color, Modifier, Style};
+
+use crate::serialize;
+
+/// what a colour is used for, the renderer only ever asks for one of these
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Role {
+    /// the border of the panel with focus and the boxes being typed in
+    Focus,
+    /// the > and the line its on
+    Cursor,
+    /// song names, and whats playing in the playbar
+    Title,
+    /// the mod songs are added with, and keys in the help
+    Mods,
+    Favourite,
+    Rating,
+    /// the part of the progress bar already played
+    Played,
+    Kiai,
+    /// songs still to come from a playlist and hints
+    Dim,
+    /// headings in the stats
+    Heading,
+    Visualizer,
+}
+
+impl Role {
+    pub const ALL: [Role; 11] = [
+        Role::Focus,
+        Role::Cursor,
+        Role::Title,
+        Role::Mods,
+        Role::Favourite,
+        Role::Rating,
+        Role::Played,
+        Role::Kiai,
+        Role::Dim,
+        Role::Heading,
+        Role::Visualizer,
+    ];
+
+    /// the name used for it in the theme file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Focus => "focus",
+            Role::Cursor => "cursor",
+            Role::Title => "title",
+            Role::Mods => "mods",
+            Role::Favourite => "favourite",
+            Role::Rating => "rating",
+            Role::Played => "played",
+            Role::Kiai => "kiai",
+            Role::Dim => "dim",
+            Role::Heading => "heading",
+            Role::Visualizer => "visualizer",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|r| r.name() == name)
+    }
+}
+
+/// the colours things have always been
+const DEFAULT_THEME: &[(Role, &str)] = &[
+    (Role::Focus, "yellow"),
+    (Role::Cursor, "red"),
+    (Role::Title, "green"),
+    (Role::Mods, "cyan"),
+    (Role::Favourite, "magenta"),
+    (Role::Rating, "yellow"),
+    (Role::Played, "green"),
+    (Role::Kiai, "magenta"),
+    (Role::Dim, "darkgray"),
+    (Role::Heading, "yellow bold"),
+    (Role::Visualizer, "cyan"),
+];
+
+/// no colour at all, everything is told apart by bold and reversed text instead
+const MONOCHROME_THEME: &[(Role, &str)] = &[
+    (Role::Focus, "bold"),
+    (Role::Cursor, "reversed"),
+    (Role::Title, ""),
+    (Role::Mods, "italic"),
+    (Role::Favourite, "bold"),
+    (Role::Rating, ""),
+    (Role::Played, "bold"),
+    (Role::Kiai, "reversed"),
+    (Role::Dim, "dim"),
+    (Role::Heading, "bold underlined"),
+    (Role::Visualizer, ""),
+];
+
+/// the bright versions of the default colours, and bold where it helps
+const HIGH_CONTRAST_THEME: &[(Role, &str)] = &[
+    (Role::Focus, "lightyellow bold"),
+    (Role::Cursor, "lightred bold"),
+    (Role::Title, "white bold"),
+    (Role::Mods, "lightcyan"),
+    (Role::Favourite, "lightmagenta"),
+    (Role::Rating, "lightyellow"),
+    (Role::Played, "lightgreen bold"),
+    (Role::Kiai, "lightmagenta bold"),
+    (Role::Dim, "gray"),
+    (Role::Heading, "white bold underlined"),
+    (Role::Visualizer, "lightcyan"),
+];
+
+/// solarized from the 256 colour palette
+const SOLARIZED_THEME: &[(Role, &str)] = &[
+    (Role::Focus, "136"),
+    (Role::Cursor, "160"),
+    (Role::Title, "64"),
+    (Role::Mods, "37"),
+    (Role::Favourite, "125"),
+    (Role::Rating, "136"),
+    (Role::Played, "64"),
+    (Role::Kiai, "61"),
+    (Role::Dim, "240"),
+    (Role::Heading, "166 bold"),
+    (Role::Visualizer, "33"),
+];
+
+/// gruvbox in truecolor, terminals without it get the nearest of the 256
+const GRUVBOX_THEME: &[(Role, &str)] = &[
+    (Role::Focus, "#fabd2f"),
+    (Role::Cursor, "#fb4934"),
+    (Role::Title, "#b8bb26"),
+    (Role::Mods, "#83a598"),
+    (Role::Favourite, "#d3869b"),
+    (Role::Rating, "#fabd2f"),
+    (Role::Played, "#b8bb26"),
+    (Role::Kiai, "#d3869b"),
+    (Role::Dim, "#928374"),
+    (Role::Heading, "#fe8019 bold"),
+    (Role::Visualizer, "#8ec07c"),
+];
+
+/// every bundled theme by name
+pub const THEMES: [(&str, &[(Role, &str)]); 5] = [
+    ("default", DEFAULT_THEME),
+    ("monochrome", MONOCHROME_THEME),
+    ("high_contrast", HIGH_CONTRAST_THEME),
+    ("solarized", SOLARIZED_THEME),
+    ("gruvbox", GRUVBOX_THEME),
+];
+
+/// THE THEME FILE, A BUNDLED THEME TO START FROM AND THEN ANY ROLES COLOURED DIFFERENTLY
+#[derive(Deserialize)]
+struct ThemeConfig {
+    #[serde(default)]
+    theme: Option<String>,
+    #[serde(flatten)]
+    roles: HashMap<String, String>,
+}
+
+/// A COLOUR BY NAME, 0 TO 255 FROM THE 256 COLOUR PALETTE, OR #rrggbb
+fn parse_color(word: &str) -> Option<Color> {
+    if let Some(hex) = word.strip_prefix('#') {
+        let channel = |i: usize| hex.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+        return match hex.len() {
+            6 => Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?)),
+            _ => None,
+        };
+    }
+    if let Ok(index) = word.parse() {
+        return Some(Color::Indexed(index));
+    }
+    let color = match word {
+        "reset" | "default" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
+
+/// READS A STYLE LIKE "yellow bold" OR "#fe8019 on 236", THE COLOUR AFTER on IS THE BACKGROUND
+pub fn parse_style(text: &str) -> Result<Style, String> {
+    let mut style = Style::default();
+    let mut background = false;
+    let dangling_on = || format!("'on' needs a colour after it in '{}'", text);
+    for word in text.split_whitespace().map(|w| w.to_lowercase()) {
+        let modifier = match word.as_str() {
+            "on" if !background => {
+                background = true;
+                continue;
+            }
+            "on" => return Err(dangling_on()),
+            "bold" => Modifier::BOLD,
+            "dim" => Modifier::DIM,
+            "italic" => Modifier::ITALIC,
+            "underlined" => Modifier::UNDERLINED,
+            "reversed" => Modifier::REVERSED,
+            _ => {
+                let color = parse_color(&word).ok_or_else(|| format!("unknown colour '{}' in '{}'", word, text))?;
+                style = match std::mem::take(&mut background) {
+                    true => style.bg(color),
+                    false => style.fg(color),
+                };
+                continue;
+            }
+        };
+        if background {
+            return Err(dangling_on());
+        }
+        style = style.add_modifier(modifier);
+    }
+    match background {
+        true => Err(dangling_on()),
+        false => Ok(style),
+    }
+}
+
+/// THE NEAREST OF THE 256 PALETTE, FROM THE 6x6x6 CUBE OR THE GREYS WHICHEVER IS CLOSER
+fn nearest_indexed(r: u8, g: u8, b: u8) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let nearest_level = |c: u8| (0..6).min_by_key(|i| (LEVELS[*i] as i32 - c as i32).abs()).unwrap_or(0);
+    let (ri, gi, bi) = (nearest_level(r), nearest_level(g), nearest_level(b));
+    let cube = (LEVELS[ri], LEVELS[gi], LEVELS[bi]);
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let grey_i = ((average.saturating_sub(8) + 5) / 10).min(23) as u8;
+    let grey = 8 + grey_i * 10;
+
+    let distance = |(cr, cg, cb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(cr, r) + d(cg, g) + d(cb, b)
+    };
+    match distance((grey, grey, grey)) < distance(cube) {
+        true => 232 + grey_i,
+        false => 16 + 36 * ri as u8 + 6 * gi as u8 + bi as u8,
+    }
+}
+
+/// terminals say they can do 24 bit colour through COLORTERM
+fn supports_truecolor() -> bool {
+    env::var("COLORTERM").is_ok_and(|c| c == "truecolor" || c == "24bit")
+}
+
+/// https://no-color.org, set to anything but empty turns colour off
+fn no_color() -> bool {
+    env::var("NO_COLOR").is_ok_and(|c| !c.is_empty())
+}
+
+/// THE STYLE FOR EVERY ROLE
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub name: String,
+    styles: HashMap<Role, Style>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::named("default").unwrap()
+    }
+}
+
+impl Theme {
+    /// A BUNDLED THEME, MONOCHROME INSTEAD IF NO_COLOR IS SET
+    pub fn named(name: &str) -> Option<Self> {
+        let (name, table) = match no_color() {
+            true => THEMES[1],
+            false => *THEMES.iter().find(|(n, _)| *n == name)?,
+        };
+        let mut theme = Self { name: name.to_string(), styles: HashMap::new() };
+        for (role, style) in table {
+            // the themes are written by hand so a typo in one should show up straight away
+            let style = parse_style(style).unwrap_or_else(|e| panic!("bad theme style: {}", e));
+            theme.set(*role, style);
+        }
+        Some(theme)
+    }
+
+    /// A MISSING FILE MEANS THE DEFAULT THEME, A BAD ONE IS AN ERROR
+    pub fn load(path: &str) -> Result<Self, String> {
+        let Some(config) = serialize::load_config::<ThemeConfig>(path)? else {
+            return Ok(Self::default());
+        };
+
+        let name = config.theme.as_deref().unwrap_or("default");
+        let mut theme = Self::named(name).ok_or_else(|| format!("unknown theme '{}'", name))?;
+        for (role_name, style) in config.roles {
+            let role = Role::from_name(&role_name).ok_or_else(|| format!("unknown theme role '{}'", role_name))?;
+            theme.set(role, parse_style(&style)?);
+        }
+        Ok(theme)
+    }
+
+    /// colours are dropped under NO_COLOR and brought down to 256 where truecolor isnt supported
+    fn set(&mut self, role: Role, mut style: Style) {
+        if no_color() {
+            style.fg = None;
+            style.bg = None;
+        } else if !supports_truecolor() {
+            let downgrade = |c: Option<Color>| match c {
+                Some(Color::Rgb(r, g, b)) => Some(Color::Indexed(nearest_indexed(r, g, b))),
+                c => c,
+            };
+            style.fg = downgrade(style.fg);
+            style.bg = downgrade(style.bg);
+        }
+        self.styles.insert(role, style);
+    }
+
+    pub fn style(&self, role: Role) -> Style {
+        self.styles.get(&role).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn styles_have_a_foreground_and_a_background() {
+        let style = parse_style("#fe8019 on 236").unwrap();
+        assert_eq!(style.fg, Some(Color::Rgb(0xfe, 0x80, 0x19)));
+        assert_eq!(style.bg, Some(Color::Indexed(236)));
+
+        let style = parse_style("Yellow BOLD on black").unwrap();
+        assert_eq!(style.fg, Some(Color::Yellow));
+        assert_eq!(style.bg, Some(Color::Black));
+        assert!(style.add_modifier.contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn bad_styles_are_errors() {
+        assert!(parse_style("purple").is_err());
+        assert!(parse_style("#fe80").is_err());
+        assert!(parse_style("yellow on").is_err());
+        assert!(parse_style("yellow on bold").is_err());
+        assert!(parse_style("on on red").is_err());
+        assert_eq!(parse_color("256"), None);
+    }
+
+    #[test]
+    fn greys_stay_grey() {
+        for level in [18, 48, 128, 188, 238] {
+            let index = nearest_indexed(level, level, level);
+            assert!((232..=255).contains(&index), "{} went to {}", level, index);
+        }
+        assert_eq!(nearest_indexed(0xff, 0, 0), 196);
+        assert_eq!(nearest_indexed(0, 0, 0), 16);
+    }
+
+    #[test]
+    fn bundled_themes_parse() {
+        for (name, table) in THEMES {
+            for (role, style) in table {
+                assert!(parse_style(style).is_ok(), "{} {:?}: {}", name, role, style);
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use crate::osu::Entry;
+use crate::player::{Player, Playlist, PlaybackState};
+
+/// how many edits can be undone before the oldest are forgotten
+const UNDO_KEPT: usize = 100;
+
+/// ONE PIECE OF STATE AS IT WAS ON ONE SIDE OF AN EDIT, UNDOING PUTS THE BEFORE SIDE BACK
+#[derive(Clone, Debug)]
+pub enum Snapshot {
+    Queue(Vec<Entry>),
+    History(Vec<Entry>),
+    /// only the mod and rate of the playing song, it isnt restarted
+    Playing(Entry),
+    PlayingPlaylist(Option<usize>),
+    /// the playbars copy of a loaded playlist
+    Playback(usize, PlaybackState),
+    Songs(usize, Vec<Entry>),
+    Name(usize, String),
+    Query(usize, Option<String>),
+    /// none means there is no playlist at the index on this side, so it gets removed
+    Playlist(usize, Option<Box<Playlist>>),
+}
+
+impl Snapshot {
+    /// THE SAME PIECE OF STATE AS IT IS NOW
+    pub fn retake(&self, player: &Player, playlists: &[Playlist]) -> Snapshot {
+        match self {
+            Snapshot::Queue(_) => Snapshot::Queue(player.queue.clone()),
+            Snapshot::History(_) => Snapshot::History(player.history.clone()),
+            Snapshot::Playing(e) => Snapshot::Playing(player.playing.clone().unwrap_or_else(|| e.clone())),
+            Snapshot::PlayingPlaylist(_) => Snapshot::PlayingPlaylist(player.playing_playlist),
+            Snapshot::Playback(i, s) => Snapshot::Playback(*i, playlists.get(*i).map_or_else(|| s.clone(), |p| p.playback_state())),
+            Snapshot::Songs(i, _) => Snapshot::Songs(*i, playlists.get(*i).map(|p| p.songs.clone()).unwrap_or_default()),
+            Snapshot::Name(i, _) => Snapshot::Name(*i, playlists.get(*i).map(|p| p.name.clone()).unwrap_or_default()),
+            Snapshot::Query(i, _) => Snapshot::Query(*i, playlists.get(*i).and_then(|p| p.query.clone())),
+            Snapshot::Playlist(i, _) => Snapshot::Playlist(*i, playlists.get(*i).cloned().map(Box::new)),
+        }
+    }
+
+    /// things like the queue move on by themselves, so an edit is only undone if nothing has since
+    fn is_current(&self, player: &Player, playlists: &[Playlist]) -> bool {
+        match self {
+            Snapshot::Queue(q) => player.queue == *q,
+            Snapshot::History(h) => player.history == *h,
+            Snapshot::Playing(e) => player.playing.as_ref() == Some(e),
+            Snapshot::PlayingPlaylist(i) => player.playing_playlist == *i,
+            Snapshot::Playback(i, s) => playlists.get(*i).is_some_and(|p| p.playback_state() == *s),
+            Snapshot::Songs(i, songs) => playlists.get(*i).is_some_and(|p| p.songs == *songs),
+            Snapshot::Name(i, name) => playlists.get(*i).is_some_and(|p| p.name == *name),
+            Snapshot::Query(i, query) => playlists.get(*i).is_some_and(|p| p.query == *query),
+            // playlists shift around when one goes, so only a playlist that should be there is checked
+            Snapshot::Playlist(i, Some(playlist)) => playlists.get(*i).is_some_and(|p| p.name == playlist.name),
+            Snapshot::Playlist(i, None) => *i <= playlists.len(),
+        }
+    }
+
+    fn put(self, player: &mut Player, playlists: &mut Vec<Playlist>) {
+        match self {
+            Snapshot::Queue(q) => player.queue = q,
+            Snapshot::History(h) => player.history = h,
+            Snapshot::Playing(e) => player.restore_playing(e),
+            Snapshot::PlayingPlaylist(i) => player.playing_playlist = i,
+            Snapshot::Playback(i, s) => playlists[i].restore_playback(s),
+            Snapshot::Songs(i, songs) => playlists[i].songs = songs,
+            Snapshot::Name(i, name) => playlists[i].name = name,
+            Snapshot::Query(i, query) => playlists[i].query = query,
+            Snapshot::Playlist(i, Some(playlist)) => {
+                playlists.insert(i, *playlist);
+                player.playlist_inserted(i);
+            }
+            Snapshot::Playlist(i, None) => {
+                playlists.remove(i);
+                player.playlist_removed(i);
+            }
+        }
+    }
+}
+
+/// AN EDIT THAT CAN BE UNDONE, WITH WHAT IT CHANGED ON EITHER SIDE
+#[derive(Clone, Debug)]
+struct Edit {
+    name: String,
+    before: Vec<Snapshot>,
+    after: Vec<Snapshot>,
+}
+
+/// THE EDITS THAT CAN BE UNDONE AND THE UNDONE ONES THAT CAN BE REDONE
+#[derive(Default)]
+pub struct UndoStack {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl UndoStack {
+    /// a new edit means anything undone before it cant be redone any more
+    pub fn record(&mut self, name: String, before: Vec<Snapshot>, after: Vec<Snapshot>) {
+        self.redo.clear();
+        self.undo.push(Edit { name, before, after });
+        if self.undo.len() > UNDO_KEPT {
+            self.undo.remove(0);
+        }
+    }
+
+    /// GIVES BACK WHAT WAS UNDONE, OR WHY IT COULDNT BE
+    pub fn undo(&mut self, player: &mut Player, playlists: &mut Vec<Playlist>) -> Result<String, String> {
+        let edit = self.undo.pop().ok_or_else(|| String::from("nothing to undo"))?;
+        if !edit.after.iter().all(|s| s.is_current(player, playlists)) {
+            return Err(format!("can't undo {}, it has changed since", edit.name));
+        }
+        for snapshot in edit.before.iter().cloned() {
+            snapshot.put(player, playlists);
+        }
+        let name = edit.name.clone();
+        self.redo.push(edit);
+        Ok(name)
+    }
+
+    pub fn redo(&mut self, player: &mut Player, playlists: &mut Vec<Playlist>) -> Result<String, String> {
+        let edit = self.redo.pop().ok_or_else(|| String::from("nothing to redo"))?;
+        if !edit.before.iter().all(|s| s.is_current(player, playlists)) {
+            return Err(format!("can't redo {}, it has changed since", edit.name));
+        }
+        for snapshot in edit.after.iter().cloned() {
+            snapshot.put(player, playlists);
+        }
+        let name = edit.name.clone();
+        self.undo.push(edit);
+        Ok(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osu::Mod;
+
+    fn entries(ids: &[&str]) -> Vec<Entry> {
+        ids.iter().map(|id| Entry::new(id.to_string(), Mod::NoMod)).collect()
+    }
+
+    fn playlists(names: &[&str]) -> Vec<Playlist> {
+        names.iter().map(|n| Playlist::new_loaded(n.to_string(), entries(&["a", "b"]))).collect()
+    }
+
+    fn names(playlists: &[Playlist]) -> Vec<&str> {
+        playlists.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    /// makes the edit to the songs of the first playlist and records it
+    fn edit_songs(stack: &mut UndoStack, player: &Player, playlists: &mut [Playlist], songs: &[&str]) {
+        let before = Snapshot::Songs(0, playlists[0].songs.clone());
+        playlists[0].songs = entries(songs);
+        let after = before.retake(player, playlists);
+        stack.record(format!("set {}", songs.join("")), vec![before], vec![after]);
+    }
+
+    #[test]
+    fn undo_and_redo_go_back_and_forth() {
+        let (mut stack, mut player, mut playlists) = (UndoStack::default(), Player::silent(), playlists(&["gym"]));
+        edit_songs(&mut stack, &player, &mut playlists, &["c"]);
+        edit_songs(&mut stack, &player, &mut playlists, &["d"]);
+
+        assert_eq!(stack.undo(&mut player, &mut playlists), Ok(String::from("set d")));
+        assert_eq!(playlists[0].songs, entries(&["c"]));
+        assert_eq!(stack.undo(&mut player, &mut playlists), Ok(String::from("set c")));
+        assert_eq!(playlists[0].songs, entries(&["a", "b"]));
+        assert!(stack.undo(&mut player, &mut playlists).is_err());
+
+        assert_eq!(stack.redo(&mut player, &mut playlists), Ok(String::from("set c")));
+        assert_eq!(stack.redo(&mut player, &mut playlists), Ok(String::from("set d")));
+        assert_eq!(playlists[0].songs, entries(&["d"]));
+        assert_eq!(stack.redo(&mut player, &mut playlists), Err(String::from("nothing to redo")));
+    }
+
+    #[test]
+    fn a_new_edit_clears_redo() {
+        let (mut stack, mut player, mut playlists) = (UndoStack::default(), Player::silent(), playlists(&["gym"]));
+        edit_songs(&mut stack, &player, &mut playlists, &["c"]);
+        stack.undo(&mut player, &mut playlists).unwrap();
+        edit_songs(&mut stack, &player, &mut playlists, &["d"]);
+        assert_eq!(stack.redo(&mut player, &mut playlists), Err(String::from("nothing to redo")));
+        assert_eq!(stack.undo(&mut player, &mut playlists), Ok(String::from("set d")));
+        assert_eq!(playlists[0].songs, entries(&["a", "b"]));
+    }
+
+    #[test]
+    fn changed_state_isnt_undone() {
+        let (mut stack, mut player, mut playlists) = (UndoStack::default(), Player::silent(), playlists(&["gym"]));
+        edit_songs(&mut stack, &player, &mut playlists, &["c"]);
+        // changed without being recorded, like the queue moving on
+        playlists[0].songs = entries(&["e"]);
+        assert_eq!(stack.undo(&mut player, &mut playlists), Err(String::from("can't undo set c, it has changed since")));
+        assert_eq!(playlists[0].songs, entries(&["e"]));
+    }
+
+    #[test]
+    fn deleted_playlists_come_back_in_place() {
+        let (mut stack, mut player, mut playlists) = (UndoStack::default(), Player::silent(), playlists(&["a", "b", "c"]));
+        player.current_playlist = Some(2);
+
+        let before = Snapshot::Playlist(1, Some(Box::new(playlists[1].clone())));
+        playlists.remove(1);
+        player.playlist_removed(1);
+        // the playlist after it moves into its place, so the after side is given rather than retaken
+        stack.record(String::from("deleted b"), vec![before], vec![Snapshot::Playlist(1, None)]);
+        assert_eq!(names(&playlists), vec!["a", "c"]);
+        assert_eq!(player.current_playlist, Some(1));
+
+        stack.undo(&mut player, &mut playlists).unwrap();
+        assert_eq!(names(&playlists), vec!["a", "b", "c"]);
+        assert_eq!(player.current_playlist, Some(2));
+
+        stack.redo(&mut player, &mut playlists).unwrap();
+        assert_eq!(names(&playlists), vec!["a", "c"]);
+        assert_eq!(player.current_playlist, Some(1));
+    }
+
+    #[test]
+    fn new_playlists_are_removed_again() {
+        let (mut stack, mut player, mut playlists) = (UndoStack::default(), Player::silent(), playlists(&["a", "b"]));
+        player.current_playlist = Some(1);
+
+        let before = Snapshot::Playlist(0, None);
+        playlists.insert(0, Playlist::new_empty(String::from("new")));
+        player.playlist_inserted(0);
+        let after = before.retake(&player, &playlists);
+        stack.record(String::from("made new"), vec![before], vec![after]);
+        assert_eq!(player.current_playlist, Some(2));
+
+        stack.undo(&mut player, &mut playlists).unwrap();
+        assert_eq!(names(&playlists), vec!["a", "b"]);
+        assert_eq!(player.current_playlist, Some(1));
+
+        stack.redo(&mut player, &mut playlists).unwrap();
+        assert_eq!(names(&playlists), vec!["new", "a", "b"]);
+        assert_eq!(player.current_playlist, Some(2));
+    }
+}
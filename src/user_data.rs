@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::io;
+
+use serde::{Serialize, Deserialize};
+
+use crate::osu::SongId;
+use crate::serialize;
+
+/// the most stars a song can be given
+pub const MAX_RATING: u8 = 5;
+
+/// what the user has said about one song, none of it can be worked out from the beatmap
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SongData {
+    #[serde(default)]
+    pub favourite: bool,
+    /// 1 to 5 stars, 0 is unrated
+    #[serde(default)]
+    pub rating: u8,
+    /// our own tags, lowercase with no repeats, nothing to do with the mappers ones
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub note: String,
+}
+
+impl SongData {
+    fn is_empty(&self) -> bool {
+        !self.favourite && self.rating == 0 && self.tags.is_empty() && self.note.is_empty()
+    }
+}
+
+/// SPLITS TYPED TAGS ON SPACES AND COMMAS, LOWERCASED AND WITHOUT REPEATS
+pub fn parse_tags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in text.split([' ', ',']).filter(|t| !t.is_empty()) {
+        let tag = tag.to_lowercase();
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// FAVOURITES, RATINGS, TAGS AND NOTES, KEPT APART FROM THE SONG CACHE SO A RESCAN NEVER LOSES THEM
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UserData {
+    #[serde(default)]
+    songs: HashMap<SongId, SongData>,
+}
+
+impl UserData {
+    /// a missing file is fine, but a broken one is an error so it doesnt get saved over
+    pub fn load(path: &str) -> Result<Self, String> {
+        serialize::load_config(path).map(Option::unwrap_or_default)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        serialize::serialize(self, path)
+    }
+
+    pub fn song(&self, id: &str) -> Option<&SongData> {
+        self.songs.get(id)
+    }
+
+    pub fn is_favourite(&self, id: &str) -> bool {
+        self.song(id).is_some_and(|s| s.favourite)
+    }
+
+    pub fn rating(&self, id: &str) -> u8 {
+        self.song(id).map_or(0, |s| s.rating)
+    }
+
+    pub fn tags(&self, id: &str) -> &[String] {
+        self.song(id).map_or(&[], |s| &s.tags)
+    }
+
+    pub fn note(&self, id: &str) -> &str {
+        self.song(id).map_or("", |s| &s.note)
+    }
+
+    /// GIVES BACK WHETHER THE SONG IS NOW A FAVOURITE
+    pub fn toggle_favourite(&mut self, id: &str) -> bool {
+        self.edit(id, |s| s.favourite = !s.favourite);
+        self.is_favourite(id)
+    }
+
+    /// 0 CLEARS THE RATING, ANYTHING ABOVE 5 IS TREATED AS 5
+    pub fn set_rating(&mut self, id: &str, rating: u8) {
+        self.edit(id, |s| s.rating = rating.min(MAX_RATING));
+    }
+
+    pub fn set_tags(&mut self, id: &str, tags: Vec<String>) {
+        self.edit(id, |s| s.tags = tags);
+    }
+
+    pub fn set_note(&mut self, id: &str, note: String) {
+        self.edit(id, |s| s.note = note.trim().to_string());
+    }
+
+    /// songs with nothing left on them are dropped so the file only holds what was set
+    fn edit(&mut self, id: &str, change: impl FnOnce(&mut SongData)) {
+        let song = self.songs.entry(id.to_string()).or_default();
+        change(song);
+        if song.is_empty() {
+            self.songs.remove(id);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rodio::{Sample, Source};
+use rodio::cpal::Sample as _;
+
+/// how many mono samples the fft is run over, has to be a power of two
+pub const FFT_SIZE: usize = 1024;
+/// how many samples are collected before the shared buffer is locked
+const FLUSH_SIZE: usize = 256;
+
+/// what the visualizer panel is currently showing
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VisualizerMode {
+    Off,
+    Bars,
+    Wave,
+}
+
+impl VisualizerMode {
+    pub fn next(self) -> Self {
+        match self {
+            VisualizerMode::Off => VisualizerMode::Bars,
+            VisualizerMode::Bars => VisualizerMode::Wave,
+            VisualizerMode::Wave => VisualizerMode::Off,
+        }
+    }
+}
+
+/// the most recent samples sent to the sink, mixed down to mono
+/// also counts how many frames have been played to work out the position in the song
+pub struct SampleBuffer {
+    pub samples: VecDeque<f32>,
+    pub sample_rate: u32,
+    pub frames_played: u64,
+}
+
+pub type SharedBuffer = Arc<Mutex<SampleBuffer>>;
+
+pub fn new_buffer() -> SharedBuffer {
+    Arc::new(Mutex::new(SampleBuffer {
+        samples: VecDeque::with_capacity(FFT_SIZE),
+        sample_rate: 44100,
+        frames_played: 0,
+    }))
+}
+
+impl SampleBuffer {
+    /// returns the newest samples, oldest first
+    pub fn latest(&self, amount: usize) -> Vec<f32> {
+        let start = self.samples.len().saturating_sub(amount);
+        self.samples.range(start..).copied().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.frames_played = 0;
+    }
+
+    /// how much of the song has been played, ignoring the speed of the sink
+    pub fn position(&self) -> Duration {
+        Duration::from_secs_f64(self.frames_played as f64 / self.sample_rate.max(1) as f64)
+    }
+}
+
+/// SOURCE WHICH COPIES EVERYTHING IT PLAYS INTO A SHARED BUFFER
+pub struct Tap<S> {
+    inner: S,
+    buffer: SharedBuffer,
+    pending: Vec<f32>,
+    frame_sum: f32,
+    frame_pos: u16,
+}
+
+impl<S> Tap<S> where S: Source, S::Item: Sample {
+    pub fn new(inner: S, buffer: SharedBuffer) -> Self {
+        if let Ok(mut b) = buffer.lock() {
+            b.clear();
+            b.sample_rate = inner.sample_rate();
+        }
+        Self {
+            inner,
+            buffer,
+            pending: Vec::with_capacity(FLUSH_SIZE),
+            frame_sum: 0.0,
+            frame_pos: 0,
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Ok(mut b) = self.buffer.lock() {
+            b.frames_played += self.pending.len() as u64;
+            b.samples.extend(self.pending.drain(..));
+            while b.samples.len() > FFT_SIZE {
+                b.samples.pop_front();
+            }
+        }
+        self.pending.clear();
+    }
+}
+
+impl<S> Iterator for Tap<S> where S: Source, S::Item: Sample {
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<S::Item> {
+        let sample = self.inner.next()?;
+
+        // mix every frame down to one value
+        self.frame_sum += sample.to_f32();
+        self.frame_pos += 1;
+        if self.frame_pos >= self.inner.channels().max(1) {
+            self.pending.push(self.frame_sum / self.frame_pos as f32);
+            self.frame_sum = 0.0;
+            self.frame_pos = 0;
+        }
+        if self.pending.len() >= FLUSH_SIZE {
+            self.flush();
+        }
+        Some(sample)
+    }
+}
+
+impl<S> Source for Tap<S> where S: Source, S::Item: Sample {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+/// IN PLACE RADIX 2 FFT, LENGTH HAS TO BE A POWER OF TWO
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + len / 2;
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// TURNS THE LATEST SAMPLES INTO BAR HEIGHTS BETWEEN 0 AND 100
+pub fn spectrum(samples: &[f32], bars: usize) -> Vec<u64> {
+    if samples.len() < FFT_SIZE || bars == 0 {
+        return vec![0; bars];
+    }
+
+    // hann window to stop the edges from leaking everywhere
+    let mut re: Vec<f32> = samples[samples.len() - FFT_SIZE..].iter().enumerate().map(|(i, s)| {
+        s * 0.5 * (1.0 - (2.0 * PI * i as f32 / (FFT_SIZE - 1) as f32).cos())
+    }).collect();
+    let mut im = vec![0.0; FFT_SIZE];
+    fft(&mut re, &mut im);
+
+    // bins are grouped logarithmically so the bass doesnt get squashed
+    let max_bin = (FFT_SIZE / 2) as f32;
+    (0..bars).map(|bar| {
+        let low = max_bin.powf(bar as f32 / bars as f32) as usize;
+        let high = (max_bin.powf((bar + 1) as f32 / bars as f32) as usize).max(low + 1);
+        let magnitude = (low..high.min(FFT_SIZE / 2))
+            .map(|i| (re[i] * re[i] + im[i] * im[i]).sqrt())
+            .fold(0.0, f32::max);
+
+        // decibels mapped from -60..0 onto 0..100
+        let db = 20.0 * (magnitude / (FFT_SIZE as f32 / 4.0)).max(1e-6).log10();
+        ((db + 60.0) / 60.0 * 100.0).clamp(0.0, 100.0) as u64
+    }).collect()
+}